@@ -0,0 +1,20 @@
+//! parser throughput on a large generated trace file
+//!
+//! run with `cargo bench --bench parse`
+
+use icache_sim::trace::{generate_large, parse_throughput};
+
+fn main() {
+    for min_bytes in [64 * 1024, 1024 * 1024] {
+        let input = generate_large(0, min_bytes);
+        let bytes_per_second = parse_throughput(&input, 10)
+            .map_err(|e| format!("generated trace failed to parse: {e}"))
+            .unwrap();
+
+        println!(
+            "trace.rs grammar, {} KB: {:.2} MB/s",
+            input.len() / 1024,
+            bytes_per_second / (1024.0 * 1024.0)
+        );
+    }
+}
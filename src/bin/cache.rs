@@ -1,17 +1,83 @@
+use std::io::Write;
+
 use icache_sim::lru::LruCache;
-use icache_sim::simulation::{Params, Simulation};
+use icache_sim::simulation::{Params, Radix, Simulation, SimulationError};
+use icache_sim::trace::TraceFile;
 
-fn main() {
-    // https://developer.arm.com/documentation/102199/0001/Memory-System/Level-1-caches?lang=en
-    let mut lru_cache: LruCache<128, 4, 64> = LruCache::new();
+/// precompiled cache geometries selectable via the `ICACHE_SIM_CONFIG` environment variable
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Profile {
+    /// 16KB, 2-way, 32B lines
+    CortexM7,
+    /// 32KB, 2-way, 64B lines
+    CortexA53,
+    /// 32KB, 4-way, 64B lines
+    Custom32k,
+}
+
+impl Profile {
+    const ENV_VAR: &str = "ICACHE_SIM_CONFIG";
+    const ALL: [Profile; 3] = [Profile::CortexM7, Profile::CortexA53, Profile::Custom32k];
+
+    fn name(self) -> &'static str {
+        match self {
+            Profile::CortexM7 => "cortex-m7",
+            Profile::CortexA53 => "cortex-a53",
+            Profile::Custom32k => "custom-32k",
+        }
+    }
+
+    /// falls back to `custom-32k` if no profile is given
+    fn select(config: Option<&str>) -> Result<Self, String> {
+        let Some(config) = config else {
+            return Ok(Profile::Custom32k);
+        };
 
+        Self::ALL
+            .into_iter()
+            .find(|profile| profile.name() == config.trim())
+            .ok_or_else(|| {
+                format!(
+                    "unknown cache profile '{config}', expected one of: {}",
+                    Self::ALL.map(Profile::name).join(", ")
+                )
+            })
+    }
+}
+
+/// loops expanding to more instructions are reported before simulating
+const LOOP_WARNING_CAP: usize = 10_000_000;
+
+fn main() {
     let Some(filename) = std::env::args().nth(1) else {
         println!("no argument for filename given");
         return;
     };
 
-    let log_memory_accesses = std::env::args().any(|arg| &arg == "--trace");
+    let explain = std::env::args().any(|arg| &arg == "--explain");
+    let log_memory_accesses = explain || std::env::args().any(|arg| &arg == "--trace");
     let skip_cache_description = std::env::args().any(|arg| &arg == "--skip-cache-desc");
+    let radix =
+        match std::env::args().find_map(|arg| arg.strip_prefix("--radix=").map(String::from)) {
+            None => Radix::Hex,
+            Some(radix) => match radix.as_str() {
+                "hex" => Radix::Hex,
+                "dec" => Radix::Dec,
+                "bin" => Radix::Bin,
+                _ => {
+                    println!("unknown radix '{radix}', expected one of: hex, dec, bin");
+                    return;
+                }
+            },
+        };
+
+    let profile = match Profile::select(std::env::var(Profile::ENV_VAR).ok().as_deref()) {
+        Ok(profile) => profile,
+        Err(e) => {
+            println!("{e}");
+            return;
+        }
+    };
 
     let current_dir = std::env::current_dir()
         .map_err(|e| format!("unable to get current directory: {e}"))
@@ -20,24 +86,108 @@ fn main() {
         .map_err(|e| format!("failed to read file: {e}"))
         .unwrap();
 
+    println!("Cache Profile: {}", profile.name());
+    let trace_file = TraceFile::try_from(file_content.as_str())
+        .map_err(|e| SimulationError::Parse(e.into_owned()));
+    if let Ok(trace_file) = &trace_file {
+        let mut metadata: Vec<_> = trace_file.metadata().iter().collect();
+        metadata.sort();
+        for (key, value) in metadata {
+            println!("{key}: {value}");
+        }
+        for warning in trace_file.loop_warnings(LOOP_WARNING_CAP) {
+            println!("warning: {warning}");
+        }
+        for warning in trace_file.unreachable_warnings() {
+            println!("warning: {warning}");
+        }
+        for warning in trace_file.overlapping_ranges() {
+            println!("warning: {warning}");
+        }
+    }
+    match profile {
+        // https://developer.arm.com/documentation/ddi0489/latest/
+        Profile::CortexM7 => run(
+            &mut LruCache::<256, 2, 32>::new(),
+            trace_file,
+            log_memory_accesses,
+            explain,
+            radix,
+            skip_cache_description,
+        ),
+        // https://developer.arm.com/documentation/ddi0500/latest/
+        Profile::CortexA53 => run(
+            &mut LruCache::<256, 2, 64>::new(),
+            trace_file,
+            log_memory_accesses,
+            explain,
+            radix,
+            skip_cache_description,
+        ),
+        // https://developer.arm.com/documentation/102199/0001/Memory-System/Level-1-caches?lang=en
+        Profile::Custom32k => run(
+            &mut LruCache::<128, 4, 64>::new(),
+            trace_file,
+            log_memory_accesses,
+            explain,
+            radix,
+            skip_cache_description,
+        ),
+    }
+}
+
+fn run<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+    lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+    trace_file: Result<TraceFile<'_>, SimulationError>,
+    log_memory_accesses: bool,
+    explain: bool,
+    radix: Radix,
+    skip_cache_description: bool,
+) {
     if !skip_cache_description {
         println!("{}", lru_cache.format_info());
     }
 
-    match Simulation::<1_600>::simulate(&mut lru_cache, &file_content, log_memory_accesses) {
+    match trace_file.and_then(|trace_file| {
+        Simulation::<1_600>::simulate_trace_file(lru_cache, trace_file, log_memory_accesses)
+    }) {
         Ok(simulation_results) => {
-            println!("{}", Simulation::memory_accesses(&simulation_results));
-            println!(
-                "{}",
-                Simulation::compare(
-                    &simulation_results,
-                    Params {
-                        cycles_hit: 1,
-                        cycles_miss: 25
-                    }
-                )
-            )
+            if explain {
+                for simulation_result in &simulation_results {
+                    println!(
+                        "{}:\n{}\n",
+                        simulation_result.name(),
+                        simulation_result.format_explained_with(radix)
+                    );
+                }
+            } else {
+                println!("{}", Simulation::memory_accesses(&simulation_results));
+            }
+            let params = Params::builder()
+                .cycles_hit(1)
+                .cycles_miss(25)
+                .build()
+                .unwrap();
+            let mut stdout = std::io::stdout().lock();
+            Simulation::write_compare(&simulation_results, params, &mut stdout)
+                .and_then(|()| writeln!(stdout))
+                .map_err(|e| format!("failed to write report: {e}"))
+                .unwrap();
         }
         Err(e) => println!("{e}"),
     };
 }
+
+#[cfg(test)]
+mod test {
+    use super::Profile;
+
+    #[test]
+    fn select_profile() {
+        assert_eq!(Profile::select(None), Ok(Profile::Custom32k));
+        assert_eq!(Profile::select(Some("cortex-m7")), Ok(Profile::CortexM7));
+        assert_eq!(Profile::select(Some("cortex-a53")), Ok(Profile::CortexA53));
+        assert_eq!(Profile::select(Some("custom-32k")), Ok(Profile::Custom32k));
+        assert!(Profile::select(Some("cortex-x1")).is_err());
+    }
+}
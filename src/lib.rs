@@ -1,6 +1,7 @@
 pub mod lru;
+pub mod parse;
 pub mod simulation;
-mod trace;
+pub mod trace;
 
 #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
 use wasm_bindgen::prelude::*;
@@ -19,21 +20,27 @@ pub fn run_simulation(
     // https://developer.arm.com/documentation/102199/0001/Memory-System/Level-1-caches?lang=en
     let mut lru_cache: LruCache<128, 4, 64> = LruCache::new();
 
+    let params = match Params::builder()
+        .cycles_hit(cycles_hit)
+        .cycles_miss(cycles_miss)
+        .build()
+    {
+        Ok(params) => params,
+        Err(e) => return e,
+    };
+
     let mut result = Vec::new();
     result.push(lru_cache.format_info());
 
     match Simulation::<1_600>::simulate(&mut lru_cache, trace, log_memory_accesses) {
         Ok(simulation_results) => {
             result.push(Simulation::memory_accesses(&simulation_results));
-            result.push(Simulation::compare(
-                &simulation_results,
-                Params {
-                    cycles_hit,
-                    cycles_miss,
-                },
-            ));
+            match Simulation::compare(&simulation_results, params) {
+                Ok(comparison) => result.push(comparison),
+                Err(e) => return e.to_string(),
+            }
         }
-        Err(e) => return e,
+        Err(e) => return e.to_string(),
     };
 
     result.join("\n")
@@ -1,21 +1,76 @@
 use std::array;
+use std::collections::HashMap;
+use std::ops::Range;
 
+use crate::parse::parse_integer;
 use crate::simulation::CacheHit;
 
 /// ## const generics
 /// - `SETS`: number of sets in case
 /// - `WAYS`: number of cache-lines in a set
 /// - `LINE_SIZE`: number of bytes in a cache-line
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LruCache<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize = 1> {
     offset_width: usize,
     set_index_width: usize,
     set_index_mask: usize,
-    sets: [CacheSet<WAYS>; SETS],
+    /// number of accesses so far, used as the timestamp of [`CacheLine::age`]
+    clock: u64,
+    fill_order: InitialFillOrder,
+    insertion_policy: InsertionPolicy,
+    indexing_mode: IndexingMode,
+    /// ways each client may fill, bit `i` stands for way `i`. clients without a mask may fill all ways
+    way_masks: HashMap<usize, u64>,
+    /// client of the following accesses
+    client: usize,
+    sets: [CacheSet<WAYS, LINE_SIZE>; SETS],
+}
+
+/// recency a newly filled cache-line starts with
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum InsertionPolicy {
+    /// most recently used
+    #[default]
+    Mru,
+    /// least recently used, the line is the next eviction candidate unless it is referenced again.
+    /// this keeps streaming accesses from evicting lines that are reused
+    Lru,
+}
+
+/// how the set index is derived from an address
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum IndexingMode {
+    /// the set index bits of the address
+    #[default]
+    Modulo,
+    /// the set index bits xor the lowest tag bits,
+    /// spreads power-of-two strides that would map to the same set over all sets
+    XorHash,
+}
+
+/// order in which the lines of a set are filled while the set is cold
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum InitialFillOrder {
+    /// lowest line index first
+    #[default]
+    Sequential,
+    /// highest line index first
+    Reverse,
+    /// like `Sequential` but set `n` starts at line `n % WAYS`
+    RoundRobin,
 }
 
 impl<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize> LruCache<SETS, WAYS, LINE_SIZE> {
+    /// the geometry and the address bit layout, each field of the layout with its bit range
     pub fn format_info(&self) -> String {
+        // `width` bits starting at bit `low`, fields without bits have no range
+        let field = |width: usize, low: usize, name: &str| match width {
+            0 => format!("0 {name} bits"),
+            _ => format!("{width} {name} bits [{}:{low}]", low + width - 1),
+        };
+        let tag_width =
+            std::mem::size_of::<usize>() * 8 - (self.set_index_width + self.offset_width);
+
         [
             "LRU Cache:",
             &format!("\tTotal Size: {}B", LINE_SIZE * WAYS * SETS),
@@ -23,20 +78,47 @@ impl<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize> LruCache<SETS
             &format!("\tWays {WAYS}"),
             &format!("\tLine-Size: {LINE_SIZE}B"),
             &format!(
-                "\t| {} tag bits | {} set bits | {} offset bits |\n",
-                std::mem::size_of::<usize>() * 8 - (self.set_index_width + self.offset_width),
-                self.set_index_width,
-                self.offset_width
+                "\t| {} | {} | {} |\n",
+                field(tag_width, self.set_index_width + self.offset_width, "tag"),
+                field(self.set_index_width, self.offset_width, "set"),
+                field(self.offset_width, 0, "offset")
             ),
         ]
         .join("\n")
     }
 
     pub fn new() -> Self {
+        Self::with_initial_fill_order(InitialFillOrder::default())
+    }
+
+    pub fn with_initial_fill_order(fill_order: InitialFillOrder) -> Self {
+        let (offset_width, set_index_width, set_index_mask) = Self::address_layout();
+
+        Self {
+            offset_width,
+            set_index_width,
+            set_index_mask,
+            clock: 0,
+            fill_order,
+            insertion_policy: InsertionPolicy::default(),
+            indexing_mode: IndexingMode::default(),
+            way_masks: HashMap::new(),
+            client: 0,
+            sets: array::from_fn(|set_index| CacheSet::new(fill_order, set_index)),
+        }
+    }
+
+    /// `(offset_width, set_index_width, set_index_mask)` derived from the geometry
+    fn address_layout() -> (usize, usize, usize) {
         // for e.g. 64 different sets we need to index 0..=63
         // the number of bits required to represent that number is log2(64 - 1) + 1
+        // a single set (fully-associative) or a single byte per line needs no bits at all
         const fn required_bits(i: usize) -> usize {
-            (i - 1).ilog2() as usize + 1
+            if i <= 1 {
+                0
+            } else {
+                (i - 1).ilog2() as usize + 1
+            }
         }
 
         const {
@@ -44,30 +126,172 @@ impl<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize> LruCache<SETS
                 required_bits(SETS) + required_bits(LINE_SIZE) <= std::mem::size_of::<usize>() * 8,
                 "not enough bits in adress to index all elements in the cache"
             );
+            assert!(
+                LINE_SIZE <= 128,
+                "the accessed bytes of a cache-line are tracked in a u128"
+            );
         }
 
         let offset_width = required_bits(LINE_SIZE);
         let set_index_width = required_bits(SETS);
         let set_index_mask = !(!0usize << set_index_width);
 
-        Self {
-            offset_width,
-            set_index_width,
-            set_index_mask,
-            sets: array::from_fn(|_| CacheSet::new()),
-        }
+        (offset_width, set_index_width, set_index_mask)
+    }
+
+    pub fn with_insertion_policy(mut self, insertion_policy: InsertionPolicy) -> Self {
+        self.insertion_policy = insertion_policy;
+        self
+    }
+
+    pub fn with_indexing_mode(mut self, indexing_mode: IndexingMode) -> Self {
+        self.indexing_mode = indexing_mode;
+        self
+    }
+
+    /// restricts the lines `client` may replace on a miss to the ways set in `way_mask`,
+    /// it can still hit in all ways
+    ///
+    /// panics if `way_mask` does not contain any of the ways
+    pub fn with_way_mask(mut self, client: usize, way_mask: u64) -> Self {
+        assert!(
+            (0..WAYS.min(64)).any(|way| way_mask & (1 << way) != 0),
+            "way mask {way_mask:#b} contains none of the {WAYS} ways"
+        );
+
+        self.way_masks.insert(client, way_mask);
+        self
     }
 
+    /// the client whose way mask applies to the following accesses, initially client 0
+    pub fn set_client(&mut self, client: usize) {
+        self.client = client;
+    }
+
+    /// clears all sets, the access count and the current client,
+    /// the configuration (fill order, policies, way masks) is kept
     pub fn reset(&mut self) {
-        self.sets = array::from_fn(|_| CacheSet::new());
+        self.clock = 0;
+        self.client = 0;
+        self.sets = array::from_fn(|set_index| CacheSet::new(self.fill_order, set_index));
+    }
+
+    /// like [`LruCache::reset`] but also re-derives the address layout (offset and set index bits)
+    /// from the geometry, leaving an empty cache as if it was just constructed
+    pub fn rebuild(&mut self) {
+        (self.offset_width, self.set_index_width, self.set_index_mask) = Self::address_layout();
+        self.reset();
     }
 
     pub fn get(&mut self, address: usize) -> CacheHit {
-        let set_index = (address >> self.offset_width) & self.set_index_mask;
-        let tag = address >> (self.set_index_width + self.offset_width);
+        self.get_bytes(address, 1)
+    }
+
+    /// a single access to the `length` bytes starting at `address`
+    ///
+    /// all bytes have to be in the same cache-line
+    pub fn get_bytes(&mut self, address: usize, length: usize) -> CacheHit {
+        let (set_index, tag) = self.decompose(address);
         // println!("{address:#X}: set={set_index}, tag={tag}");
 
-        self.sets.get_mut(set_index).unwrap().get(address, tag)
+        let offset = address % LINE_SIZE;
+        debug_assert!(
+            length > 0 && offset + length <= LINE_SIZE,
+            "access crosses a cache-line boundary"
+        );
+
+        self.clock += 1;
+        self.sets.get_mut(set_index).unwrap().get(
+            address,
+            tag,
+            offset..offset + length,
+            self.clock,
+            self.insertion_policy,
+            self.way_masks.get(&self.client).copied(),
+        )
+    }
+
+    /// the tags of the lines in set `set_index` from least to most recently used,
+    /// `None` for lines that are not filled
+    pub fn set_recency(&self, set_index: usize) -> Vec<Option<usize>> {
+        let set = self.sets.get(set_index).unwrap();
+
+        set.lru
+            .iter()
+            .rev()
+            .map(|&line_idx| set.lines.get(line_idx).unwrap().tag)
+            .collect()
+    }
+
+    /// evicts the cache-line containing `address`, returns whether it was resident
+    pub fn invalidate(&mut self, address: usize) -> bool {
+        let (set_index, tag) = self.decompose(address);
+
+        self.sets.get_mut(set_index).unwrap().invalidate(tag)
+    }
+
+    /// checks if the cache-line containing `address` is resident without updating the LRU state
+    pub fn contains(&self, address: usize) -> bool {
+        let (set_index, tag) = self.decompose(address);
+
+        self.sets.get(set_index).unwrap().contains(tag)
+    }
+
+    /// per set and way the timestamp of the last access, `None` for lines that were never filled
+    ///
+    /// higher values are more recent
+    pub fn line_ages(&self) -> Vec<Vec<Option<u64>>> {
+        self.sets
+            .iter()
+            .map(|set| set.lines.iter().map(CacheLine::age).collect())
+            .collect()
+    }
+
+    /// per set and way the bytes of the line accessed during its current residency,
+    /// bit `n` is set if byte `n` of the line was accessed. `None` for lines that are not filled
+    pub fn line_utilization(&self) -> Vec<Vec<Option<u128>>> {
+        self.sets
+            .iter()
+            .map(|set| set.lines.iter().map(CacheLine::accessed_mask).collect())
+            .collect()
+    }
+
+    /// number of accesses since the cache was created or reset
+    pub fn access_count(&self) -> u64 {
+        self.clock
+    }
+
+    /// number of bytes loaded into the cache on cache-misses
+    pub fn bytes_fetched(&self) -> u64 {
+        self.sets.iter().map(|set| set.fills).sum::<u64>() * LINE_SIZE as u64
+    }
+
+    /// number of fetched bytes that were accessed while their cache-line was resident
+    pub fn bytes_used(&self) -> u64 {
+        self.sets.iter().map(CacheSet::bytes_used).sum()
+    }
+
+    /// number of cache-lines that were evicted or invalidated without ever being hit,
+    /// lines that are still resident are not counted
+    pub fn polluting_fills(&self) -> u64 {
+        self.sets.iter().map(|set| set.polluting_fills).sum()
+    }
+
+    /// percentage of fetched bytes that were actually accessed before being evicted,
+    /// `None` if nothing was fetched yet
+    pub fn fetch_efficiency(&self) -> Option<f64> {
+        let bytes_fetched = self.bytes_fetched();
+        (bytes_fetched > 0).then(|| 100.0 * self.bytes_used() as f64 / bytes_fetched as f64)
+    }
+
+    fn decompose(&self, address: usize) -> (usize, usize) {
+        let tag = address >> (self.set_index_width + self.offset_width);
+        let set_index = match self.indexing_mode {
+            IndexingMode::Modulo => (address >> self.offset_width) & self.set_index_mask,
+            IndexingMode::XorHash => ((address >> self.offset_width) ^ tag) & self.set_index_mask,
+        };
+
+        (set_index, tag)
     }
 }
 
@@ -79,24 +303,117 @@ impl<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize> Default
     }
 }
 
+/// converts a human readable cache description like `"32KB"`, 4 ways and 64B lines
+/// into the `(SETS, WAYS, LINE_SIZE)` const generics of [`LruCache`]
+///
+/// `total` is a number of bytes as accepted by [`parse_integer`], optionally with a `B`, `KB` or `MB` suffix,
+/// hexadecimal numbers can't have a `B` suffix as it would be read as their last digit.
+/// the number of sets and the line size have to be powers of two
+pub fn geometry_from_human(total: &str, ways: u32, line: u32) -> Option<(usize, usize, usize)> {
+    let total = total.trim().to_ascii_lowercase();
+    let (digits, multiplier) = if let Some(digits) = total.strip_suffix("mb") {
+        (digits, 1024 * 1024)
+    } else if let Some(digits) = total.strip_suffix("kb") {
+        (digits, 1024)
+    } else if total.starts_with("0x") {
+        // `b` is a hex digit
+        (total.as_str(), 1)
+    } else {
+        (total.strip_suffix('b').unwrap_or(&total), 1)
+    };
+    let total = parse_integer(digits)?.checked_mul(multiplier)?;
+
+    let ways = usize::try_from(ways).ok()?;
+    let line = usize::try_from(line).ok()?;
+    let set_size = ways.checked_mul(line).filter(|&set_size| set_size > 0)?;
+    if total % set_size != 0 {
+        return None;
+    }
+
+    let sets = total / set_size;
+    (sets.is_power_of_two() && line.is_power_of_two()).then_some((sets, ways, line))
+}
+
+/// `lru` holds the line indices ordered from most to least recently used
+///
+/// lines that were never filled share the same (lowest) recency,
+/// this tie is broken by the [`InitialFillOrder`]
 #[derive(Debug, Clone)]
-struct CacheSet<const WAYS: usize> {
-    lines: [CacheLine; WAYS],
+struct CacheSet<const WAYS: usize, const LINE_SIZE: usize> {
+    lines: [CacheLine<LINE_SIZE>; WAYS],
     lru: [usize; WAYS],
+    fills: u64,
+    evicted_bytes_used: u64,
+    /// lines that were evicted or invalidated without a single hit
+    polluting_fills: u64,
 }
 
-impl<const LINES: usize> CacheSet<LINES> {
-    fn new() -> Self {
+impl<const LINES: usize, const LINE_SIZE: usize> CacheSet<LINES, LINE_SIZE> {
+    fn new(fill_order: InitialFillOrder, set_index: usize) -> Self {
+        // the line filled by the n-th cold miss
+        let nth_fill = |n: usize| match fill_order {
+            InitialFillOrder::Sequential => n,
+            InitialFillOrder::Reverse => LINES - 1 - n,
+            InitialFillOrder::RoundRobin => (set_index + n) % LINES,
+        };
+
         Self {
             lines: [CacheLine {
                 address: None,
                 tag: None,
+                accessed: 0,
+                last_access: None,
+                hit: false,
             }; LINES],
-            lru: array::from_fn(|i| i),
+            lru: array::from_fn(|i| nth_fill(LINES - 1 - i)),
+            fills: 0,
+            evicted_bytes_used: 0,
+            polluting_fills: 0,
         }
     }
 
-    fn get(&mut self, address: usize, tag: usize) -> CacheHit {
+    fn bytes_used(&self) -> u64 {
+        self.evicted_bytes_used + self.lines.iter().map(CacheLine::bytes_used).sum::<u64>()
+    }
+
+    fn contains(&self, tag: usize) -> bool {
+        self.lines.iter().any(|line| line.tag == Some(tag))
+    }
+
+    /// the line becomes the least recently used one, like lines that were never filled
+    fn invalidate(&mut self, tag: usize) -> bool {
+        let Some(line_idx) = self.lines.iter().position(|line| line.tag == Some(tag)) else {
+            return false;
+        };
+
+        let line = self.lines.get_mut(line_idx).unwrap();
+        self.evicted_bytes_used += line.bytes_used();
+        if !line.hit {
+            self.polluting_fills += 1;
+        }
+        *line = CacheLine {
+            address: None,
+            tag: None,
+            accessed: 0,
+            last_access: None,
+            hit: false,
+        };
+
+        let meta_idx = self.lru.iter().position(|&idx| idx == line_idx).unwrap();
+        self.lru[meta_idx..].rotate_left(1);
+
+        true
+    }
+
+    fn get(
+        &mut self,
+        address: usize,
+        tag: usize,
+        offsets: Range<usize>,
+        now: u64,
+        insertion_policy: InsertionPolicy,
+        way_mask: Option<u64>,
+    ) -> CacheHit {
         // linear search for cache_line with tag
         let cache_line = self
             .lines
@@ -122,19 +439,58 @@ impl<const LINES: usize> CacheSet<LINES> {
                 }
                 *self.lru.get_mut(0).unwrap() = tmp;
 
+                let line = self.lines.get_mut(line_idx).unwrap();
+                line.access(offsets);
+                line.last_access = Some(now);
+                line.hit = true;
+
                 CacheHit::Hit
             }
-            // Cache-Miss: replace least recently used cache-line and set it as the most recently used
+            // Cache-Miss: replace least recently used cache-line and move it to the insertion position
             None => {
-                self.lru.rotate_right(1);
-                let lru = *self.lru.first().unwrap();
+                // the least recently used line the client may replace, lines that were never
+                // filled are at the tail so an empty way the client may use is picked first
+                let victim = match way_mask {
+                    None => LINES - 1,
+                    Some(way_mask) => (0..LINES)
+                        .rev()
+                        .find(|&meta_idx| {
+                            let way = self.lru[meta_idx];
+                            way < 64 && way_mask & (1 << way) != 0
+                        })
+                        .unwrap(),
+                };
+                // lines that were never filled stay behind the new line so they are filled first
+                let position = match insertion_policy {
+                    InsertionPolicy::Mru => 0,
+                    InsertionPolicy::Lru => {
+                        let filled = self.lines.iter().filter(|line| line.tag.is_some()).count();
+                        if victim >= filled { filled } else { filled - 1 }
+                    }
+                };
+                if victim >= position {
+                    self.lru[position..=victim].rotate_right(1);
+                } else {
+                    self.lru[victim..=position].rotate_left(1);
+                }
+                let lru = self.lru[position];
 
                 let lru_line = self.lines.get_mut(lru).unwrap();
                 let prev = lru_line.address;
+                self.evicted_bytes_used += lru_line.bytes_used();
+                self.fills += 1;
+                if lru_line.tag.is_some() && !lru_line.hit {
+                    self.polluting_fills += 1;
+                }
+
                 *lru_line = CacheLine {
                     address: Some(address),
                     tag: Some(tag),
+                    accessed: 0,
+                    last_access: Some(now),
+                    hit: false,
                 };
+                lru_line.access(offsets);
 
                 CacheHit::Miss { prev }
             }
@@ -142,8 +498,348 @@ impl<const LINES: usize> CacheSet<LINES> {
     }
 }
 
+/// bit `n` of `accessed` is set if byte `n` of the cache-line was accessed since it was filled
 #[derive(Debug, Copy, Clone)]
-pub struct CacheLine {
+pub struct CacheLine<const LINE_SIZE: usize> {
     address: Option<usize>,
     tag: Option<usize>,
+    accessed: u128,
+    last_access: Option<u64>,
+    /// whether the line hit since it was filled
+    hit: bool,
+}
+
+impl<const LINE_SIZE: usize> CacheLine<LINE_SIZE> {
+    /// timestamp of the most recent access, `None` if the line was never filled
+    pub fn age(&self) -> Option<u64> {
+        self.last_access
+    }
+
+    /// the bytes accessed since the line was filled, `None` if it is not filled
+    pub fn accessed_mask(&self) -> Option<u128> {
+        self.tag.map(|_| self.accessed)
+    }
+
+    fn access(&mut self, offsets: Range<usize>) {
+        let bytes = u128::MAX
+            .checked_shr(128 - offsets.len() as u32)
+            .unwrap_or(0);
+        self.accessed |= bytes << offsets.start;
+    }
+
+    fn bytes_used(&self) -> u64 {
+        u64::from(self.accessed.count_ones())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{IndexingMode, InitialFillOrder, InsertionPolicy, LruCache, geometry_from_human};
+    use crate::simulation::CacheHit;
+
+    #[test]
+    fn contains_resident_lines() {
+        let mut lru_cache: LruCache<2, 2, 4> = LruCache::new();
+
+        // 0x0, 0x8 and 0x10 all map to set 0
+        lru_cache.get(0x0);
+        lru_cache.get(0x8);
+        lru_cache.get(0x10);
+
+        assert!(!lru_cache.contains(0x0));
+        assert!(lru_cache.contains(0x8));
+        assert!(lru_cache.contains(0x13));
+        assert!(!lru_cache.contains(0x4));
+    }
+
+    #[test]
+    fn cold_fill_uses_lowest_line_index() {
+        let mut lru_cache: LruCache<2, 4, 4> = LruCache::new();
+
+        lru_cache.get(0x0);
+        assert_eq!(lru_cache.sets[0].lines[0].address, Some(0x0));
+        assert!(
+            lru_cache.sets[0].lines[1..]
+                .iter()
+                .all(|l| l.address.is_none())
+        );
+
+        lru_cache.get(0x8);
+        lru_cache.get(0x10);
+        lru_cache.get(0x18);
+        let addresses = lru_cache.sets[0].lines.map(|l| l.address);
+        assert_eq!(addresses, [Some(0x0), Some(0x8), Some(0x10), Some(0x18)]);
+    }
+
+    #[test]
+    fn fetch_efficiency_of_single_access() {
+        let mut lru_cache: LruCache<2, 1, 64> = LruCache::new();
+        assert_eq!(lru_cache.fetch_efficiency(), None);
+
+        lru_cache.get(0x0);
+        assert_eq!(lru_cache.bytes_fetched(), 64);
+        assert_eq!(lru_cache.bytes_used(), 1);
+
+        // evicts the first line, which only had a single byte accessed
+        lru_cache.get(0x80);
+        lru_cache.get(0x81);
+        assert_eq!(lru_cache.bytes_fetched(), 128);
+        assert_eq!(lru_cache.bytes_used(), 3);
+        assert!(lru_cache.fetch_efficiency().unwrap() < 3.0);
+    }
+
+    #[test]
+    fn human_readable_geometry() {
+        assert_eq!(geometry_from_human("32KB", 4, 64), Some((128, 4, 64)));
+        assert_eq!(geometry_from_human("32768", 4, 64), Some((128, 4, 64)));
+        assert_eq!(geometry_from_human("16kb", 2, 32), Some((256, 2, 32)));
+        assert_eq!(geometry_from_human("0x8000", 4, 64), Some((128, 4, 64)));
+        assert_eq!(geometry_from_human("0x20KB", 4, 64), Some((128, 4, 64)));
+        // 0x8000B and 0x1B bytes, not 0x8000 and 0x1
+        assert_eq!(geometry_from_human("0x8000B", 4, 64), None);
+        assert_eq!(geometry_from_human("0x1B", 1, 1), None);
+        assert_eq!(geometry_from_human("0x1", 1, 1), Some((1, 1, 1)));
+
+        // 120 sets
+        assert_eq!(geometry_from_human("30KB", 4, 64), None);
+        assert_eq!(geometry_from_human("100B", 4, 64), None);
+        assert_eq!(geometry_from_human("32KB", 0, 64), None);
+        assert_eq!(geometry_from_human("32 bytes", 1, 1), None);
+    }
+
+    #[test]
+    fn accessed_line_is_newest() {
+        let mut lru_cache: LruCache<2, 2, 4> = LruCache::new();
+        assert_eq!(
+            lru_cache.line_ages(),
+            vec![vec![None, None], vec![None, None]]
+        );
+
+        lru_cache.get(0x0);
+        lru_cache.get(0x8);
+        lru_cache.get(0x4);
+        lru_cache.get(0x0);
+
+        let ages = lru_cache.line_ages();
+        assert_eq!(ages, vec![vec![Some(4), Some(2)], vec![Some(3), None]]);
+        assert_eq!(ages.iter().flatten().max(), Some(&Some(4)));
+    }
+
+    #[test]
+    fn address_bit_layout() {
+        let info = LruCache::<128, 4, 64>::new().format_info();
+        assert!(info.ends_with(&format!(
+            "\t| {} tag bits [{}:13] | 7 set bits [12:6] | 6 offset bits [5:0] |\n",
+            usize::BITS - 13,
+            usize::BITS - 1
+        )));
+
+        // fully-associative with single byte lines
+        let mut lru_cache: LruCache<1, 2, 1> = LruCache::new();
+        let info = lru_cache.format_info();
+        assert!(info.ends_with(&format!(
+            "\t| {} tag bits [{}:0] | 0 set bits | 0 offset bits |\n",
+            usize::BITS,
+            usize::BITS - 1
+        )));
+
+        lru_cache.get(0x0);
+        lru_cache.get(0x1234);
+        assert!(lru_cache.contains(0x0));
+        assert!(lru_cache.contains(0x1234));
+        assert!(!lru_cache.contains(0x1));
+    }
+
+    #[test]
+    fn initial_fill_orders() {
+        let mut lru_cache: LruCache<2, 4, 4> =
+            LruCache::with_initial_fill_order(InitialFillOrder::Reverse);
+        lru_cache.get(0x0);
+        assert_eq!(lru_cache.sets[0].lines[3].address, Some(0x0));
+        lru_cache.get(0x8);
+        assert_eq!(lru_cache.sets[0].lines[2].address, Some(0x8));
+
+        // survives a reset
+        lru_cache.reset();
+        lru_cache.get(0x0);
+        assert_eq!(lru_cache.sets[0].lines[3].address, Some(0x0));
+
+        let mut lru_cache: LruCache<2, 4, 4> =
+            LruCache::with_initial_fill_order(InitialFillOrder::RoundRobin);
+        lru_cache.get(0x0);
+        lru_cache.get(0x4);
+        assert_eq!(lru_cache.sets[0].lines[0].address, Some(0x0));
+        assert_eq!(lru_cache.sets[1].lines[1].address, Some(0x4));
+    }
+
+    #[test]
+    fn lru_insertion_protects_reused_line() {
+        // 0x0 is reused after a scan over 0x8, 0x10 and 0x18, which all map to set 0
+        let scan_then_reuse = |lru_cache: &mut LruCache<2, 2, 4>| {
+            [0x0, 0x0, 0x8, 0x10, 0x18, 0x0]
+                .into_iter()
+                .filter(|&address| lru_cache.get(address) == CacheHit::Hit)
+                .count()
+        };
+
+        assert_eq!(scan_then_reuse(&mut LruCache::new()), 1);
+        assert_eq!(
+            scan_then_reuse(&mut LruCache::new().with_insertion_policy(InsertionPolicy::Lru)),
+            2
+        );
+    }
+
+    #[test]
+    fn invalidate_single_line() {
+        let mut lru_cache: LruCache<2, 2, 4> = LruCache::new();
+        lru_cache.get(0x0);
+        lru_cache.get(0x8);
+
+        assert!(lru_cache.invalidate(0x1));
+        assert!(!lru_cache.invalidate(0x1));
+        assert!(!lru_cache.contains(0x0));
+        assert!(lru_cache.contains(0x8));
+
+        // the invalidated line is filled next instead of evicting 0x8
+        assert_eq!(lru_cache.get(0x10), CacheHit::Miss { prev: None });
+        assert!(lru_cache.contains(0x8));
+    }
+
+    #[test]
+    fn recency_order_of_set() {
+        let mut lru_cache: LruCache<2, 4, 4> = LruCache::new();
+        assert_eq!(lru_cache.set_recency(0), vec![None; 4]);
+
+        // tags 0, 1 and 2 in set 0, 0x4 is in set 1
+        lru_cache.get(0x0);
+        lru_cache.get(0x8);
+        lru_cache.get(0x10);
+        lru_cache.get(0x4);
+        lru_cache.get(0x0);
+
+        assert_eq!(
+            lru_cache.set_recency(0),
+            vec![None, Some(1), Some(2), Some(0)]
+        );
+        assert_eq!(lru_cache.set_recency(1), vec![None, None, None, Some(0)]);
+    }
+
+    #[test]
+    fn xor_indexing_spreads_power_of_two_stride() {
+        // a stride of SETS * LINE_SIZE maps every access to set 0 with modulo indexing
+        let strided_hits = |lru_cache: &mut LruCache<4, 1, 4>| {
+            (0..2)
+                .flat_map(|_| (0..4).map(|i| i * 0x10))
+                .filter(|&address| lru_cache.get(address) == CacheHit::Hit)
+                .count()
+        };
+
+        assert_eq!(strided_hits(&mut LruCache::new()), 0);
+        assert_eq!(
+            strided_hits(&mut LruCache::new().with_indexing_mode(IndexingMode::XorHash)),
+            4
+        );
+    }
+
+    #[test]
+    fn access_count_of_hits_and_misses() {
+        let mut lru_cache: LruCache<2, 2, 4> = LruCache::new();
+        let (hits, misses) = [0x0, 0x4, 0x0, 0x10, 0x8, 0x0, 0x1]
+            .into_iter()
+            .map(|address| lru_cache.get(address))
+            .fold((0, 0), |(hits, misses), cache_hit| match cache_hit {
+                CacheHit::Hit => (hits + 1, misses),
+                CacheHit::Miss { .. } => (hits, misses + 1),
+            });
+
+        assert_eq!((hits, misses), (2, 5));
+        assert_eq!(lru_cache.access_count(), hits + misses);
+
+        lru_cache.reset();
+        assert_eq!(lru_cache.access_count(), 0);
+    }
+
+    #[test]
+    fn way_partitioning() {
+        // client 0 may only fill way 0, client 1 the ways 1 to 3
+        let mut lru_cache: LruCache<1, 4, 4> = LruCache::new()
+            .with_way_mask(0, 0b0001)
+            .with_way_mask(1, 0b1110);
+
+        let mut misses = [0; 2];
+        for _ in 0..4 {
+            for (client, addresses) in [(0, [0x0, 0x10].as_slice()), (1, &[0x100, 0x110, 0x120])] {
+                lru_cache.set_client(client);
+                for &address in addresses {
+                    if lru_cache.get(address).is_miss() {
+                        misses[client] += 1;
+                    }
+                }
+            }
+        }
+
+        // two lines thrash in a single way, three lines fit into three ways
+        assert_eq!(misses, [8, 3]);
+    }
+
+    #[test]
+    fn rebuild_leaves_an_empty_cache() {
+        let mut lru_cache: LruCache<4, 2, 16> = LruCache::new();
+        lru_cache.get(0x0);
+        lru_cache.get(0x40);
+        lru_cache.offset_width = 0;
+        lru_cache.set_index_mask = 0;
+
+        lru_cache.rebuild();
+        assert_eq!(lru_cache.offset_width, 4);
+        assert_eq!(lru_cache.set_index_width, 2);
+        assert_eq!(lru_cache.set_index_mask, 0b11);
+        assert_eq!(lru_cache.access_count(), 0);
+        assert!(!lru_cache.contains(0x0));
+
+        assert!(lru_cache.get(0x0).is_miss());
+        assert!(lru_cache.get(0xF).is_hit());
+        assert!(lru_cache.get(0x10).is_miss());
+    }
+
+    #[test]
+    fn line_utilization_of_partial_access() {
+        let mut lru_cache: LruCache<2, 2, 64> = LruCache::new();
+
+        lru_cache.get_bytes(0x8, 8);
+        let utilization = lru_cache.line_utilization();
+        let mask = utilization[0].iter().find_map(|mask| *mask).unwrap();
+        assert_eq!(mask.count_ones(), 8);
+        assert_eq!(mask, 0xFF00);
+
+        let mut lru_cache: LruCache<1, 1, 128> = LruCache::new();
+        lru_cache.get_bytes(0x0, 128);
+        assert_eq!(lru_cache.line_utilization()[0][0], Some(u128::MAX));
+        assert_eq!(lru_cache.bytes_used(), 128);
+        assert_eq!(utilization[1], [None, None]);
+    }
+
+    #[test]
+    fn lru_insertion_with_way_mask_fills_empty_ways_first() {
+        // client 0 may fill the ways 0 and 1, client 1 the ways 0 and 2
+        let mut lru_cache: LruCache<1, 4, 16> = LruCache::new()
+            .with_insertion_policy(InsertionPolicy::Lru)
+            .with_way_mask(0, 0b0011)
+            .with_way_mask(1, 0b0101);
+
+        for address in [0x0, 0x10, 0x20] {
+            assert!(lru_cache.get(address).is_miss());
+        }
+        assert!(lru_cache.contains(0x0));
+        assert!(!lru_cache.contains(0x10));
+        assert!(lru_cache.contains(0x20));
+
+        // way 2 is still empty, so nothing is evicted
+        lru_cache.set_client(1);
+        assert!(lru_cache.get(0x30).is_miss());
+        assert!(lru_cache.contains(0x0));
+        assert!(lru_cache.contains(0x20));
+        assert!(lru_cache.contains(0x30));
+        assert_eq!(lru_cache.set_recency(0)[0], None);
+    }
 }
@@ -0,0 +1,94 @@
+use std::str::FromStr;
+
+use winnow::combinator::{alt, cut_err, preceded};
+use winnow::error::StrContext;
+use winnow::token::take_while;
+use winnow::{ModalResult, Parser};
+
+/// a number with a `0x` (hexadecimal), `0b` (binary) or `0o` (octal) prefix, or a decimal number
+/// without one
+pub(crate) fn integer(input: &mut &str) -> ModalResult<usize> {
+    alt((
+        preceded(
+            "0x",
+            cut_err(take_while(1.., ('0'..='9', 'a'..='f', 'A'..='F')))
+                .try_map(|s| usize::from_str_radix(s, 16))
+                .context(StrContext::Label("hexadecimal number")),
+        ),
+        preceded(
+            "0b",
+            cut_err(take_while(1.., '0'..='1'))
+                .try_map(|s| usize::from_str_radix(s, 2))
+                .context(StrContext::Label("binary number")),
+        ),
+        preceded(
+            "0o",
+            cut_err(take_while(1.., '0'..='7'))
+                .try_map(|s| usize::from_str_radix(s, 8))
+                .context(StrContext::Label("octal number")),
+        ),
+        cut_err(decimal_integer),
+    ))
+    .parse_next(input)
+}
+
+/// a number without a radix prefix, doesn't consume a sign or any whitespace
+pub(crate) fn decimal_integer(input: &mut &str) -> ModalResult<usize> {
+    take_while(1.., '0'..='9')
+        .context(StrContext::Label("decimal number"))
+        .try_map(str::parse::<usize>)
+        .parse_next(input)
+}
+
+/// parses `literal` as an [`Integer`], it has to consist of the number only, e.g. a command line argument
+pub fn parse_integer(literal: &str) -> Option<usize> {
+    literal.parse::<Integer>().ok().map(|Integer(value)| value)
+}
+
+/// a numeric literal of the trace grammar, either decimal or with a `0x`, `0b` or `0o` prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Integer(pub usize);
+
+impl FromStr for Integer {
+    type Err = String;
+
+    fn from_str(literal: &str) -> Result<Self, Self::Err> {
+        integer
+            .parse(literal.trim())
+            .map(Integer)
+            .map_err(|_| format!("'{}' is not a valid number", literal.trim()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Integer, parse_integer};
+
+    #[test]
+    fn all_radixes() {
+        assert_eq!(parse_integer("0x1F"), Some(0x1F));
+        assert_eq!(parse_integer("0xff"), Some(0xFF));
+        assert_eq!(parse_integer("0b101"), Some(0b101));
+        assert_eq!(parse_integer("0o17"), Some(0o17));
+        assert_eq!(parse_integer("42"), Some(42));
+        assert_eq!(parse_integer("0"), Some(0));
+
+        assert_eq!(parse_integer("0x"), None);
+        assert_eq!(parse_integer("0b102"), None);
+        assert_eq!(parse_integer("0o8"), None);
+        assert_eq!(parse_integer("12ab"), None);
+        assert_eq!(parse_integer(""), None);
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!("0x40".parse(), Ok(Integer(0x40)));
+        assert_eq!(" 0b11 ".parse(), Ok(Integer(0b11)));
+        assert_eq!("0o10".parse(), Ok(Integer(0o10)));
+        assert_eq!("7".parse(), Ok(Integer(7)));
+        assert_eq!(
+            "0x".parse::<Integer>(),
+            Err(String::from("'0x' is not a valid number"))
+        );
+    }
+}
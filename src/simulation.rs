@@ -1,11 +1,169 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Write};
 use std::path::Path;
 
-use crate::{lru::LruCache, trace::TraceFile};
+use crate::{
+    lru::LruCache,
+    trace::{Instruction, TraceEvent, TraceFile, TraceParseError},
+};
 
+/// prefer [`Params::builder`] which rejects nonsensical configurations
 #[derive(Debug, Copy, Clone)]
 pub struct Params {
     pub cycles_hit: u32,
     pub cycles_miss: u32,
+    /// digits after the decimal point for times and percentages in the text output
+    pub precision: usize,
+    /// number of outstanding cache-line fills (miss status holding registers),
+    /// `None` for a blocking front end that waits for every fill
+    ///
+    /// see [`Simulation::stall_cycles`]
+    pub mshr_count: Option<usize>,
+    pub cache_model: CacheModel,
+    /// see [`Contention`]
+    pub contention: Option<Contention>,
+    /// cycles added for every instruction spanning multiple cache-lines
+    pub line_cross_penalty: u32,
+    /// order of [`Simulation::reports`] and the text output
+    pub sort_order: SortOrder,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// fastest trace first
+    #[default]
+    ByTime,
+    ByName,
+    /// the order the traces were simulated in
+    Source,
+}
+
+/// memory latency growing with the recent miss rate
+///
+/// a miss takes `cycles_miss + cycles_per_miss_rate * recent miss rate` cycles,
+/// the miss rate is taken over the last `window` accesses including the miss itself
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Contention {
+    pub window: usize,
+    pub cycles_per_miss_rate: u32,
+}
+
+/// whether hits can be serviced while a miss is outstanding
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum CacheModel {
+    /// the front end stalls for the whole latency of a miss
+    #[default]
+    Blocking,
+    /// hits are serviced during the `cycles_miss - cycles_hit` cycles a miss is outstanding,
+    /// the time of overlapping hits is free.
+    /// assumes enough hits follow each miss to fill its latency
+    HitUnderMiss,
+}
+
+impl Params {
+    pub fn builder() -> ParamsBuilder {
+        ParamsBuilder::default()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ParamsBuilder {
+    cycles_hit: u32,
+    cycles_miss: u32,
+    precision: Option<usize>,
+    mshr_count: Option<usize>,
+    cache_model: CacheModel,
+    contention: Option<Contention>,
+    line_cross_penalty: u32,
+    sort_order: SortOrder,
+}
+
+impl ParamsBuilder {
+    pub fn cycles_hit(mut self, cycles_hit: u32) -> Self {
+        self.cycles_hit = cycles_hit;
+        self
+    }
+
+    pub fn cycles_miss(mut self, cycles_miss: u32) -> Self {
+        self.cycles_miss = cycles_miss;
+        self
+    }
+
+    /// defaults to 3
+    pub fn precision(mut self, precision: usize) -> Self {
+        self.precision = Some(precision);
+        self
+    }
+
+    /// switches the timing to a non-blocking front end, see [`Simulation::stall_cycles`]
+    pub fn mshr_count(mut self, mshr_count: usize) -> Self {
+        self.mshr_count = Some(mshr_count);
+        self
+    }
+
+    /// defaults to [`CacheModel::Blocking`]
+    pub fn cache_model(mut self, cache_model: CacheModel) -> Self {
+        self.cache_model = cache_model;
+        self
+    }
+
+    /// see [`Contention`]
+    pub fn contention(mut self, window: usize, cycles_per_miss_rate: u32) -> Self {
+        self.contention = Some(Contention {
+            window,
+            cycles_per_miss_rate,
+        });
+        self
+    }
+
+    /// defaults to 0
+    pub fn line_cross_penalty(mut self, line_cross_penalty: u32) -> Self {
+        self.line_cross_penalty = line_cross_penalty;
+        self
+    }
+
+    /// defaults to [`SortOrder::ByTime`]
+    pub fn sort_order(mut self, sort_order: SortOrder) -> Self {
+        self.sort_order = sort_order;
+        self
+    }
+
+    pub fn build(self) -> Result<Params, String> {
+        if self.cycles_hit == 0 || self.cycles_miss == 0 {
+            return Err(String::from(
+                "cache-hit and cache-miss cycles must be nonzero",
+            ));
+        }
+
+        if self.cycles_miss < self.cycles_hit {
+            return Err(format!(
+                "cache-miss cycles ({}) must not be less than cache-hit cycles ({})",
+                self.cycles_miss, self.cycles_hit
+            ));
+        }
+
+        if self.mshr_count == Some(0) {
+            return Err(String::from("the number of MSHRs must be nonzero"));
+        }
+
+        if self
+            .contention
+            .is_some_and(|contention| contention.window == 0)
+        {
+            return Err(String::from("the contention window must be nonzero"));
+        }
+
+        Ok(Params {
+            cycles_hit: self.cycles_hit,
+            cycles_miss: self.cycles_miss,
+            precision: self.precision.unwrap_or(3),
+            mshr_count: self.mshr_count,
+            cache_model: self.cache_model,
+            contention: self.contention,
+            line_cross_penalty: self.line_cross_penalty,
+            sort_order: self.sort_order,
+        })
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -13,7 +171,228 @@ pub struct Simulation<const CLOCK_SPEED_MHZ: u32> {
     name: String,
     hit_count: u32,
     miss_count: u32,
-    memory_accesses: String,
+    bytes_fetched: u64,
+    bytes_used: u64,
+    /// number of instructions spanning multiple cache-lines
+    line_crossings: u32,
+    /// see [`LruCache::polluting_fills`]
+    polluting_fills: u64,
+    /// number of different bytes fetched by the instructions
+    distinct_addresses: u64,
+    /// number of different cache-lines the instructions touched, a lower bound for the misses
+    /// if the cache is larger than the trace, a trace larger than the cache can exceed its number of lines
+    distinct_lines: u64,
+    /// whether each instruction hit, the [`Contention`] latency depends on the order of the misses
+    hits: HitSequence,
+    /// only recorded if memory accesses are logged
+    memory_accesses: Option<Vec<MemoryAccess>>,
+    /// the cache the result was simulated with, `None` if it was not simulated
+    geometry: Option<Geometry>,
+    /// see [`TraceFile::measured_miss_rates`]
+    measured_miss_rate: Option<f64>,
+}
+
+/// the const generics of the [`LruCache`] a [`Simulation`] ran on
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Geometry {
+    pub sets: usize,
+    pub ways: usize,
+    pub line_size: usize,
+}
+
+impl Geometry {
+    fn of<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>() -> Self {
+        Geometry {
+            sets: SETS,
+            ways: WAYS,
+            line_size: LINE_SIZE,
+        }
+    }
+}
+
+impl std::fmt::Display for Geometry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} sets, {} ways, {}B lines",
+            self.sets, self.ways, self.line_size
+        )
+    }
+}
+
+/// a logged access of a single instruction
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub address: usize,
+    pub hit: bool,
+    /// for instructions spanning multiple cache-lines this is the classification
+    /// of the first cache-line that missed
+    pub miss_kind: Option<MissKind>,
+    /// the address stored in the cache-line that got replaced
+    pub evicted: Option<usize>,
+    /// whether the instruction spans multiple cache-lines
+    pub line_crossing: bool,
+    /// cycles spent on all accesses up to and including this one,
+    /// only set by [`Simulation::simulate_timed`]
+    pub timestamp: Option<u64>,
+}
+
+/// the three Cs
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MissKind {
+    /// the cache-line was never accessed before
+    Compulsory,
+    /// a fully associative cache of the same size would have missed as well
+    Capacity,
+    /// a fully associative cache of the same size would have hit
+    Conflict,
+}
+
+impl std::fmt::Display for MissKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MissKind::Compulsory => f.write_str("compulsory"),
+            MissKind::Capacity => f.write_str("capacity"),
+            MissKind::Conflict => f.write_str("conflict"),
+        }
+    }
+}
+
+/// one bit per instruction, set if it hit
+#[derive(Debug, Clone, Default)]
+struct HitSequence {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl HitSequence {
+    fn push(&mut self, hit: bool) {
+        if self.len.is_multiple_of(64) {
+            self.words.push(0);
+        }
+        *self.words.last_mut().unwrap() |= u64::from(hit) << (self.len % 64);
+        self.len += 1;
+    }
+
+    fn get(&self, index: usize) -> bool {
+        self.words[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len).map(|index| self.get(index))
+    }
+
+    fn clear(&mut self) {
+        self.words.clear();
+        self.len = 0;
+    }
+}
+
+impl FromIterator<bool> for HitSequence {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> Self {
+        let mut hits = Self::default();
+        hits.extend(iter);
+        hits
+    }
+}
+
+impl Extend<bool> for HitSequence {
+    fn extend<I: IntoIterator<Item = bool>>(&mut self, iter: I) {
+        for hit in iter {
+            self.push(hit);
+        }
+    }
+}
+
+/// the bytes touched per cache-line, one lookup per line instead of one per byte
+#[derive(Debug, Default)]
+struct TouchedBytes<const LINE_SIZE: usize>(HashMap<usize, [bool; LINE_SIZE]>);
+
+impl<const LINE_SIZE: usize> TouchedBytes<LINE_SIZE> {
+    /// `address..address + length` has to lie within a single cache-line
+    fn touch(&mut self, address: usize, length: usize) {
+        self.0
+            .entry(address / LINE_SIZE)
+            .or_insert([false; LINE_SIZE])[address % LINE_SIZE..][..length]
+            .fill(true);
+    }
+
+    fn extend(&mut self, other: &Self) {
+        for (&line, bytes) in &other.0 {
+            let touched = self.0.entry(line).or_insert([false; LINE_SIZE]);
+            for (touched, &byte) in touched.iter_mut().zip(bytes) {
+                *touched |= byte;
+            }
+        }
+    }
+
+    /// sets the distinct addresses and lines of `simulation` to the ones touched so far
+    fn count_into<const CLOCK_SPEED_MHZ: u32>(&self, simulation: &mut Simulation<CLOCK_SPEED_MHZ>) {
+        simulation.distinct_addresses = self
+            .0
+            .values()
+            .map(|bytes| bytes.iter().filter(|&&touched| touched).count() as u64)
+            .sum();
+        simulation.distinct_lines = self.0.len() as u64;
+    }
+}
+
+/// classifies misses by replaying all cache-line accesses on a fully associative LRU cache
+/// with the same number of cache-lines
+#[derive(Debug)]
+struct MissClassifier {
+    capacity: usize,
+    seen: HashSet<usize>,
+    last_use: HashMap<usize, u64>,
+    by_last_use: BTreeMap<u64, usize>,
+    clock: u64,
+}
+
+impl MissClassifier {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            seen: HashSet::new(),
+            last_use: HashMap::new(),
+            by_last_use: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    /// `line` is the address divided by the line size
+    fn classify(&mut self, line: usize, cache_hit: CacheHit) -> Option<MissKind> {
+        self.clock += 1;
+        let fully_associative_hit = match self.last_use.insert(line, self.clock) {
+            Some(last_use) => {
+                self.by_last_use.remove(&last_use);
+                true
+            }
+            None => false,
+        };
+        self.by_last_use.insert(self.clock, line);
+        if self.by_last_use.len() > self.capacity {
+            let (_, lru) = self.by_last_use.pop_first().unwrap();
+            self.last_use.remove(&lru);
+        }
+
+        let first_use = self.seen.insert(line);
+        match cache_hit {
+            CacheHit::Hit => None,
+            CacheHit::Miss { .. } if first_use => Some(MissKind::Compulsory),
+            CacheHit::Miss { .. } if !fully_associative_hit => Some(MissKind::Capacity),
+            CacheHit::Miss { .. } => Some(MissKind::Conflict),
+        }
+    }
+}
+
+impl MissClassifier {
+    /// the next miss of a flushed line counts as compulsory
+    fn invalidate(&mut self, line: usize) {
+        self.seen.remove(&line);
+        if let Some(last_use) = self.last_use.remove(&line) {
+            self.by_last_use.remove(&last_use);
+        }
+    }
 }
 
 impl<const CLOCK_SPEED_MHZ: u32> Simulation<CLOCK_SPEED_MHZ> {
@@ -21,12 +400,91 @@ impl<const CLOCK_SPEED_MHZ: u32> Simulation<CLOCK_SPEED_MHZ> {
         lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
         file: impl AsRef<Path>,
         log_memory_accesses: bool,
-    ) -> Result<Vec<Self>, String> {
-        let current_dir =
-            std::env::current_dir().map_err(|e| format!("unable to get current directory: {e}"))?;
+    ) -> Result<Vec<Self>, SimulationError> {
+        let current_dir = std::env::current_dir().map_err(|source| SimulationError::Io {
+            context: "unable to get current directory",
+            source,
+        })?;
+
+        let file_content = std::fs::read_to_string(current_dir.join(file)).map_err(|source| {
+            SimulationError::Io {
+                context: "failed to read file",
+                source,
+            }
+        })?;
+        Self::simulate(lru_cache, file_content.as_str(), log_memory_accesses)
+    }
+
+    /// like [`Simulation::simulate`] with logged memory accesses, each one carries the cycle timestamp
+    /// after it completed
+    ///
+    /// an access takes `cycles_hit` or `cycles_miss` cycles plus the [`Contention`] latency of a miss
+    /// rounded to whole cycles and the [`Params::line_cross_penalty`].
+    /// with [`CacheModel::HitUnderMiss`] hits are free while the latency of an earlier miss
+    /// is outstanding. with an MSHR limit a miss takes `cycles_hit` cycles plus its
+    /// [`Simulation::stall_cycles`] instead
+    pub fn simulate_timed<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        file_data: &str,
+        params: &Params,
+    ) -> Result<Vec<Self>, SimulationError> {
+        let mut simulation_results = Self::simulate(lru_cache, file_data, true)?;
+        for result in &mut simulation_results {
+            let contention_cycles: Vec<f64> = contention_cycles(&result.hits, params).collect();
+            let mshr_stalls: Option<Vec<u64>> =
+                mshr_stalls(&result.hits, params).map(Iterator::collect);
+            let memory_accesses = result.memory_accesses.as_mut().unwrap();
+            // cycles of outstanding misses that following hits overlap with
+            let mut outstanding = 0;
+            let mut now = 0;
+            for (i, (access, contention_cycles)) in memory_accesses
+                .iter_mut()
+                .zip(contention_cycles)
+                .enumerate()
+            {
+                let mut cycles = if let Some(mshr_stalls) = &mshr_stalls {
+                    let contention_cycles = if access.hit {
+                        0
+                    } else {
+                        contention_cycles.round() as u64
+                    };
+                    u64::from(params.cycles_hit) + mshr_stalls[i] + contention_cycles
+                } else if access.hit {
+                    let overlapped = outstanding.min(u64::from(params.cycles_hit));
+                    outstanding -= overlapped;
+                    u64::from(params.cycles_hit) - overlapped
+                } else {
+                    if params.cache_model == CacheModel::HitUnderMiss {
+                        outstanding +=
+                            u64::from(params.cycles_miss.saturating_sub(params.cycles_hit));
+                    }
+                    u64::from(params.cycles_miss) + contention_cycles.round() as u64
+                };
+                if access.line_crossing {
+                    cycles += u64::from(params.line_cross_penalty);
+                }
+
+                now += cycles;
+                access.timestamp = Some(now);
+            }
+        }
+
+        Ok(simulation_results)
+    }
 
-        let file_content = std::fs::read_to_string(current_dir.join(file))
-            .map_err(|e| format!("failed to read file: {e}"))?;
+    /// like [`Simulation::simulate`] but the trace file is read from `reader`
+    pub fn simulate_reader<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        mut reader: impl io::Read,
+        log_memory_accesses: bool,
+    ) -> Result<Vec<Self>, SimulationError> {
+        let mut file_content = String::new();
+        reader
+            .read_to_string(&mut file_content)
+            .map_err(|source| SimulationError::Io {
+                context: "failed to read trace",
+                source,
+            })?;
         Self::simulate(lru_cache, file_content.as_str(), log_memory_accesses)
     }
 
@@ -34,162 +492,3229 @@ impl<const CLOCK_SPEED_MHZ: u32> Simulation<CLOCK_SPEED_MHZ> {
         lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
         file_data: &str,
         log_memory_accesses: bool,
-    ) -> Result<Vec<Self>, String> {
-        let trace_file = match TraceFile::try_from(file_data) {
-            Ok(trace_file) => trace_file,
-            Err(e) => {
-                return Err(format!("failed to parse access trace file: {e}"));
-            }
-        };
+    ) -> Result<Vec<Self>, SimulationError> {
+        let trace_file =
+            TraceFile::try_from(file_data).map_err(|e| SimulationError::Parse(e.into_owned()))?;
+        Self::simulate_trace_file(lru_cache, trace_file, log_memory_accesses)
+    }
 
-        let simulation_results = trace_file
-            .into_iter()
+    /// like [`Simulation::simulate`] for an already parsed trace file
+    pub fn simulate_trace_file<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        trace_file: TraceFile<'_>,
+        log_memory_accesses: bool,
+    ) -> Result<Vec<Self>, SimulationError> {
+        let trace_file = Self::check(trace_file, false)?;
+        Self::simulate_mapped(
+            lru_cache,
+            trace_file,
+            None,
+            log_memory_accesses,
+            |_, events| Ok(events),
+        )
+    }
+
+    /// simulates every compare block of `trace_file` on the reset cache warmed up by the `preamble`
+    /// with the events `map_events` returns for the block, the `preamble` is mapped as well
+    ///
+    /// the `// @expect_instructions` count is compared with the instructions before mapping,
+    /// blocks stop after `max_instructions` instructions and then aren't compared
+    fn simulate_mapped<
+        const SETS: usize,
+        const WAYS: usize,
+        const LINE_SIZE: usize,
+        E: IntoIterator<Item = TraceEvent>,
+    >(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        trace_file: TraceFile<'_>,
+        max_instructions: Option<usize>,
+        log_memory_accesses: bool,
+        mut map_events: impl FnMut(&str, std::vec::IntoIter<TraceEvent>) -> Result<E, SimulationError>,
+    ) -> Result<Vec<Self>, SimulationError> {
+        let expected_instructions = trace_file
+            .expected_instructions()
+            .filter(|_| max_instructions.is_none());
+        let measured_miss_rates = trace_file.measured_miss_rates().clone();
+        let preamble: Option<Vec<TraceEvent>> = trace_file
+            .preamble()
+            .map(|preamble| Ok(map_events("preamble", preamble)?.into_iter().collect()))
+            .transpose()?;
+
+        let mut instructions = 0;
+        let mut simulation_results = trace_file
+            .into_events(max_instructions.unwrap_or(usize::MAX))
             .map(|(name, block)| {
+                instructions += block
+                    .as_slice()
+                    .iter()
+                    .filter(|event| matches!(event, TraceEvent::Instruction(_)))
+                    .count();
+                let block = map_events(name, block)?;
+
                 lru_cache.reset();
+                Self::warm_up(lru_cache, preamble.clone().map(Vec::into_iter));
+                Ok(Self::simulate_block(
+                    lru_cache,
+                    name,
+                    block,
+                    log_memory_accesses,
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-                block.into_iter().fold(
-                    Simulation {
-                        name: name.to_string(),
-                        hit_count: 0,
-                        miss_count: 0,
-                        memory_accesses: if log_memory_accesses {
-                            format!("{name}:\n")
-                        } else {
-                            String::new()
-                        },
-                    },
-                    |mut simulation_result, instruction| {
-                        // check all byte addresses
-                        // if we just check the start address of the instruction
-                        // we would fail to consider the case where an instruction spans multiple cache-blocks
-                        // this happens for variable size instruction sets (x86, Arm thumb)
-                        let mut hit = true;
-                        for i in 0..(instruction.length / 8) {
-                            hit &= lru_cache.get(instruction.address + i) == CacheHit::Hit;
-                        }
+        Self::check_expected_instructions(expected_instructions, instructions)?;
+        Self::set_measured_miss_rates(&measured_miss_rates, &mut simulation_results);
+        Ok(simulation_results)
+    }
 
-                        if hit {
-                            simulation_result.hit_count += 1;
-                        } else {
-                            simulation_result.miss_count += 1;
-                        }
+    /// like [`Simulation::simulate`] but the cache is only reset before the first block,
+    /// every block starts with the lines the previous one left behind
+    ///
+    /// `lru_cache` is left in its state after the last block
+    pub fn simulate_retaining<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        file_data: &str,
+        log_memory_accesses: bool,
+    ) -> Result<Vec<Self>, SimulationError> {
+        let trace_file = Self::parse(file_data, false)?;
+        let expected_instructions = trace_file.expected_instructions();
+        let measured_miss_rates = trace_file.measured_miss_rates().clone();
 
-                        if log_memory_accesses {
-                            simulation_result
-                                .memory_accesses
-                                .push_str(&format!("{:X}: hit={:?}\n", instruction.address, hit));
-                        }
+        lru_cache.reset();
+        Self::warm_up(lru_cache, trace_file.preamble());
+        let mut simulation_results: Vec<Self> = trace_file
+            .into_events(usize::MAX)
+            .map(|(name, block)| Self::simulate_block(lru_cache, name, block, log_memory_accesses))
+            .collect();
 
-                        simulation_result
-                    },
-                )
+        Self::check_expected_instructions(
+            expected_instructions,
+            Self::instruction_count(&simulation_results),
+        )?;
+        Self::set_measured_miss_rates(&measured_miss_rates, &mut simulation_results);
+        Ok(simulation_results)
+    }
+
+    /// like [`Simulation::simulate`] but `hook` is called for every simulated instruction
+    /// with its outcome, the instructions of the `preamble` are not reported
+    ///
+    /// an instruction spanning multiple cache-lines is a miss if any of them missed
+    pub fn simulate_with_hook<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        file_data: &str,
+        mut hook: impl FnMut(&Instruction, CacheHit),
+    ) -> Result<Vec<Self>, SimulationError> {
+        let trace_file = Self::parse(file_data, false)?;
+        let expected_instructions = trace_file.expected_instructions();
+        let measured_miss_rates = trace_file.measured_miss_rates().clone();
+        let preamble = trace_file.preamble();
+
+        let mut simulation_results: Vec<Self> = trace_file
+            .into_events(usize::MAX)
+            .map(|(name, block)| {
+                lru_cache.reset();
+                Self::warm_up(lru_cache, preamble.clone());
+                Self::simulate_block_with_hook(lru_cache, name, block, false, &mut hook)
             })
             .collect();
 
+        Self::check_expected_instructions(
+            expected_instructions,
+            Self::instruction_count(&simulation_results),
+        )?;
+        Self::set_measured_miss_rates(&measured_miss_rates, &mut simulation_results);
         Ok(simulation_results)
     }
 
-    fn percent_hit(&self) -> f64 {
-        100.0 * f64::from(self.hit_count) / (f64::from(self.hit_count) + f64::from(self.miss_count))
+    /// like [`Simulation::simulate`] but every block runs on its own thread with a copy of `lru_cache`
+    ///
+    /// the results are sorted by name
+    pub fn simulate_parallel<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &LruCache<SETS, WAYS, LINE_SIZE>,
+        file_data: &str,
+        log_memory_accesses: bool,
+    ) -> Result<Vec<Self>, SimulationError> {
+        let trace_file = Self::parse(file_data, false)?;
+        let expected_instructions = trace_file.expected_instructions();
+        let measured_miss_rates = trace_file.measured_miss_rates().clone();
+        let preamble = trace_file.preamble();
+
+        let mut simulation_results: Vec<Self> = std::thread::scope(|scope| {
+            let handles: Vec<_> = trace_file
+                .into_events(usize::MAX)
+                .map(|(name, block)| {
+                    let mut lru_cache = lru_cache.clone();
+                    let preamble = preamble.clone();
+                    scope.spawn(move || {
+                        lru_cache.reset();
+                        Self::warm_up(&mut lru_cache, preamble);
+                        Self::simulate_block(&mut lru_cache, name, block, log_memory_accesses)
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        simulation_results.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Self::check_expected_instructions(
+            expected_instructions,
+            Self::instruction_count(&simulation_results),
+        )?;
+        Self::set_measured_miss_rates(&measured_miss_rates, &mut simulation_results);
+        Ok(simulation_results)
     }
 
-    fn percent_miss(&self) -> f64 {
-        100.0 * f64::from(self.miss_count)
-            / (f64::from(self.hit_count) + f64::from(self.miss_count))
+    /// like [`Simulation::simulate`] but instructions starting outside of `lo..hi` are skipped entirely,
+    /// they neither count as hit or miss nor change the state of the cache.
+    /// the window applies to the `preamble` as well
+    pub fn simulate_windowed<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        file_data: &str,
+        lo: usize,
+        hi: usize,
+        log_memory_accesses: bool,
+    ) -> Result<Vec<Self>, SimulationError> {
+        let trace_file = Self::parse(file_data, false)?;
+        Self::simulate_mapped(
+            lru_cache,
+            trace_file,
+            None,
+            log_memory_accesses,
+            |_, events| {
+                Ok(events.filter(move |event| match event {
+                    TraceEvent::Instruction(instruction) => (lo..hi).contains(&instruction.address),
+                    TraceEvent::FlushLine(_)
+                    | TraceEvent::Load(_)
+                    | TraceEvent::Store(_)
+                    | TraceEvent::Client(_) => true,
+                }))
+            },
+        )
     }
 
-    pub fn format_summary(
-        &self,
-        Params {
-            cycles_hit,
-            cycles_miss,
-        }: &Params,
-    ) -> String {
-        let mut result = vec![
-            format!("Trace: {}", self.name),
-            format!(
-                "Number of Instructions: {}",
-                self.hit_count + self.miss_count
-            ),
-            format!("Hits: {}, Misses: {}", self.hit_count, self.miss_count),
-            format!("Percent Hits: {:.3}%", self.percent_hit()),
-            format!("Percent Misses: {:.3}%", self.percent_miss()),
-            format!(
-                "Assuming Clock-Speed: {CLOCK_SPEED_MHZ} MHz, Cache-Hit: {cycles_hit} cycles, Cache-Miss: {cycles_miss} cycles"
-            ),
-        ];
+    /// like [`Simulation::simulate`] but all addresses, including the ones of the `preamble`,
+    /// are moved by `offset`
+    pub fn simulate_relocated<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        file_data: &str,
+        offset: usize,
+        log_memory_accesses: bool,
+    ) -> Result<Vec<Self>, SimulationError> {
+        let trace_file = Self::parse(file_data, false)?;
+        Self::simulate_mapped(
+            lru_cache,
+            trace_file,
+            None,
+            log_memory_accesses,
+            |name, events| {
+                events
+                    .map(|event| match event {
+                        TraceEvent::Instruction(instruction) => instruction
+                            .address
+                            .checked_add(offset)
+                            .filter(|address| address.checked_add(instruction.length / 8).is_some())
+                            .map(|address| {
+                                TraceEvent::Instruction(Instruction {
+                                    address,
+                                    ..instruction
+                                })
+                            }),
+                        TraceEvent::FlushLine(address) => {
+                            address.checked_add(offset).map(TraceEvent::FlushLine)
+                        }
+                        TraceEvent::Load(address) => {
+                            address.checked_add(offset).map(TraceEvent::Load)
+                        }
+                        TraceEvent::Store(address) => {
+                            address.checked_add(offset).map(TraceEvent::Store)
+                        }
+                        TraceEvent::Client(_) => Some(event),
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| SimulationError::AddressOverflow {
+                        trace: name.to_string(),
+                        offset,
+                    })
+            },
+        )
+    }
 
-        let cycle_time_us = f64::from(CLOCK_SPEED_MHZ).recip();
-        let total_time_us = f64::from(self.hit_count) * f64::from(*cycles_hit) * cycle_time_us
-            + f64::from(self.miss_count) * f64::from(*cycles_miss) * cycle_time_us;
-        if total_time_us >= 1_000_000.0 {
-            result.push(format!("Total time: {:.3}s", total_time_us / 1_000_000.0));
-        } else if total_time_us >= 1_000.0 {
-            result.push(format!("Total time: {:.3}ms", total_time_us / 1_000.0));
-        } else {
-            result.push(format!("Total time: {:.3}us", total_time_us));
+    /// per compare block the number of instructions whose cache-lines all hit, all missed or were split
+    /// between resident and non-resident cache-lines
+    ///
+    /// split instructions only exist when instructions cross cache-line boundaries,
+    /// [`Simulation::simulate`] counts them as misses
+    pub fn simulate_split_report<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        file_data: &str,
+    ) -> Result<Vec<(String, SplitCounts)>, SimulationError> {
+        let trace_file = Self::parse(file_data, false)?;
+        let preamble = trace_file.preamble();
+
+        let split_counts = trace_file
+            .into_iter()
+            .map(|(name, block)| {
+                lru_cache.reset();
+                Self::warm_up(lru_cache, preamble.clone());
+
+                let mut split_counts = SplitCounts::default();
+                for instruction in block {
+                    let (hits, misses) = line_chunks::<LINE_SIZE>(&instruction).fold(
+                        (0, 0),
+                        |(hits, misses), (address, length)| match lru_cache
+                            .get_bytes(address, length)
+                        {
+                            CacheHit::Hit => (hits + 1, misses),
+                            CacheHit::Miss { .. } => (hits, misses + 1),
+                        },
+                    );
+
+                    match (hits, misses) {
+                        (_, 0) => split_counts.full_hit += 1,
+                        (0, _) => split_counts.full_miss += 1,
+                        _ => split_counts.split += 1,
+                    }
+                }
+
+                (name.to_string(), split_counts)
+            })
+            .collect();
+
+        Ok(split_counts)
+    }
+
+    /// like [`Simulation::simulate`] but every block stops after `max_instructions` instructions
+    ///
+    /// blocks containing a `loop(inf)` can only be simulated this way, [`Simulation::simulate`] rejects them
+    pub fn simulate_limited<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        file_data: &str,
+        max_instructions: usize,
+        log_memory_accesses: bool,
+    ) -> Result<Vec<Self>, SimulationError> {
+        let trace_file = Self::parse(file_data, true)?;
+        Self::simulate_mapped(
+            lru_cache,
+            trace_file,
+            Some(max_instructions),
+            log_memory_accesses,
+            |_, events| Ok(events),
+        )
+    }
+
+    /// simulates every compare block `runs` times, run `n` seeds the random choices with `seed + n`
+    ///
+    /// gives the mean miss rate of each block and its 95% confidence interval,
+    /// blocks without switches, `random` or `zipf` have the same miss rate in every run
+    pub fn simulate_monte_carlo<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        file_data: &str,
+        runs: usize,
+        seed: u64,
+    ) -> Result<Vec<MonteCarloResult>, SimulationError> {
+        if runs == 0 {
+            return Err(SimulationError::ZeroRuns);
+        }
+
+        // the miss rate of every run per compare block
+        let mut miss_rates: Vec<(String, Vec<f64>)> = Vec::new();
+        for run in 0..runs {
+            let trace_file = Self::parse(file_data, false)?;
+            let preamble = trace_file.preamble();
+
+            let blocks = trace_file.into_events_seeded(usize::MAX, seed.wrapping_add(run as u64));
+            for (index, (name, block)) in blocks.enumerate() {
+                lru_cache.reset();
+                Self::warm_up(lru_cache, preamble.clone());
+                let result = Self::simulate_block(lru_cache, name, block, false);
+
+                let instructions = result.hit_count + result.miss_count;
+                let miss_rate = if instructions == 0 {
+                    0.0
+                } else {
+                    f64::from(result.miss_count) / f64::from(instructions)
+                };
+                if run == 0 {
+                    miss_rates.push((name.to_string(), Vec::with_capacity(runs)));
+                }
+                miss_rates[index].1.push(miss_rate);
+            }
         }
 
-        result.join("\n")
+        Ok(miss_rates
+            .into_iter()
+            .map(|(name, miss_rates)| MonteCarloResult::new(name, &miss_rates))
+            .collect())
     }
 
-    pub fn memory_accesses(simulation_results: &[Self]) -> String {
-        simulation_results
+    /// results without a geometry are not checked
+    fn check_geometry(simulation_results: &[Self]) -> Result<(), SimulationError> {
+        let mut geometries = simulation_results
             .iter()
-            .filter(|sim| !sim.memory_accesses.is_empty())
-            .map(|sim| sim.memory_accesses.clone())
-            .collect::<String>()
+            .filter_map(|result| result.geometry.map(|geometry| (result, geometry)));
+        let Some((first, expected)) = geometries.next() else {
+            return Ok(());
+        };
+
+        match geometries.find(|(_, geometry)| *geometry != expected) {
+            Some((result, actual)) => Err(SimulationError::GeometryMismatch {
+                expected: (first.name.clone(), expected),
+                actual: (result.name.clone(), actual),
+            }),
+            None => Ok(()),
+        }
     }
 
-    pub fn compare(simulation_results: &[Self], config: Params) -> String {
-        let cycle_time_hit_us = f64::from(config.cycles_hit) * f64::from(CLOCK_SPEED_MHZ).recip();
-        let cycle_time_miss_us = f64::from(config.cycles_miss) * f64::from(CLOCK_SPEED_MHZ).recip();
-        let mut results = simulation_results
-            .iter()
-            .map(|r| {
-                (
-                    r,
-                    f64::from(r.hit_count) * cycle_time_hit_us
-                        + f64::from(r.miss_count) * cycle_time_miss_us,
-                )
-            })
-            .collect::<Vec<_>>();
+    /// sets the `// @measured_miss_rate` of each result to the one of its compare block
+    fn set_measured_miss_rates(
+        measured_miss_rates: &HashMap<&str, f64>,
+        simulation_results: &mut [Self],
+    ) {
+        for simulation_result in simulation_results {
+            simulation_result.measured_miss_rate = measured_miss_rates
+                .get(simulation_result.name.as_str())
+                .copied();
+        }
+    }
 
-        results.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
-        let Some(baseline) = results.first().map(|f| f.1) else {
-            return String::from("nothing to compare");
+    /// compares the `// @expect_instructions` count of a file with the simulated instructions
+    fn check_expected_instructions(
+        expected_instructions: Option<usize>,
+        instructions: usize,
+    ) -> Result<(), SimulationError> {
+        let Some(expected_instructions) = expected_instructions else {
+            return Ok(());
         };
 
-        results
-            .into_iter()
-            .flat_map(|(sim, time)| {
-                vec![
-                    sim.format_summary(&config),
-                    format!(
-                        "Relative Time: +{:.3}%\n",
-                        (time - baseline) / baseline * 100.0
-                    ),
-                ]
-            })
-            .collect::<Vec<_>>()
-            .join("\n")
+        if instructions != expected_instructions {
+            return Err(SimulationError::InstructionCountMismatch {
+                expected: expected_instructions,
+                actual: instructions,
+            });
+        }
+
+        Ok(())
     }
-}
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum CacheHit {
-    Hit,
-    Miss { prev: Option<usize> },
-}
+    fn instruction_count(simulation_results: &[Self]) -> usize {
+        simulation_results
+            .iter()
+            .map(|result| (result.hit_count + result.miss_count) as usize)
+            .sum()
+    }
 
-impl std::fmt::Display for CacheHit {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            CacheHit::Hit => f.write_str("Hit"),
-            CacheHit::Miss { prev } => match prev {
-                Some(prev) => f.write_fmt(format_args!("Miss prev={prev:#X}")),
-                None => f.write_str("Miss"),
-            },
+    /// unless `limited` is set, files with a `loop(inf)` in a compare block are rejected
+    fn parse(file_data: &str, limited: bool) -> Result<TraceFile<'_>, SimulationError> {
+        let trace_file =
+            TraceFile::try_from(file_data).map_err(|e| SimulationError::Parse(e.into_owned()))?;
+        Self::check(trace_file, limited)
+    }
+
+    /// see [`Simulation::parse`]
+    fn check(trace_file: TraceFile<'_>, limited: bool) -> Result<TraceFile<'_>, SimulationError> {
+        let static_stats = trace_file.static_stats();
+        if trace_file.is_empty() {
+            return Err(SimulationError::Empty);
         }
+        if static_stats.is_empty() {
+            return Err(SimulationError::NoComparableTraces);
+        }
+
+        if !limited
+            && let Some((name, _)) = static_stats.into_iter().find(|(_, stats)| stats.unbounded)
+        {
+            return Err(SimulationError::UnboundedLoop(name.to_string()));
+        }
+
+        Ok(trace_file)
+    }
+
+    /// runs the `preamble` block of a trace file, only the resulting cache state is kept
+    fn warm_up<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        preamble: Option<std::vec::IntoIter<TraceEvent>>,
+    ) {
+        if let Some(preamble) = preamble {
+            Self::simulate_block(lru_cache, "preamble", preamble, false);
+        }
+    }
+
+    /// simulates the instructions of a single block starting from the current state of `lru_cache`
+    ///
+    /// flushes invalidate cache-lines but are not counted, data accesses are ignored
+    fn simulate_block<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        name: &str,
+        events: impl IntoIterator<Item = impl Into<TraceEvent>>,
+        log_memory_accesses: bool,
+    ) -> Self {
+        Self::simulate_block_with_hook(lru_cache, name, events, log_memory_accesses, &mut |_, _| {})
+    }
+
+    /// like [`Simulation::simulate_block`] but `hook` is called with the outcome of every instruction
+    fn simulate_block_with_hook<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        name: &str,
+        events: impl IntoIterator<Item = impl Into<TraceEvent>>,
+        log_memory_accesses: bool,
+        hook: &mut impl FnMut(&Instruction, CacheHit),
+    ) -> Self {
+        Self::simulate_slice(
+            lru_cache,
+            name,
+            events,
+            log_memory_accesses,
+            hook,
+            &mut TouchedBytes::default(),
+        )
+    }
+
+    /// like [`Simulation::simulate_block_with_hook`] but the distinct addresses and lines are
+    /// counted over `touched`, which keeps the bytes touched by earlier slices of the same thread
+    fn simulate_slice<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        name: &str,
+        events: impl IntoIterator<Item = impl Into<TraceEvent>>,
+        log_memory_accesses: bool,
+        hook: &mut impl FnMut(&Instruction, CacheHit),
+        touched: &mut TouchedBytes<LINE_SIZE>,
+    ) -> Self {
+        let bytes_fetched = lru_cache.bytes_fetched();
+        let bytes_used = lru_cache.bytes_used();
+        let polluting_fills = lru_cache.polluting_fills();
+        let mut classifier = log_memory_accesses.then(|| MissClassifier::new(SETS * WAYS));
+
+        let mut simulation_result = events.into_iter().map(Into::into).fold(
+            Simulation {
+                name: name.to_string(),
+                hit_count: 0,
+                miss_count: 0,
+                bytes_fetched: 0,
+                bytes_used: 0,
+                line_crossings: 0,
+                polluting_fills: 0,
+                distinct_addresses: 0,
+                distinct_lines: 0,
+                hits: HitSequence::default(),
+                memory_accesses: log_memory_accesses.then(Vec::new),
+                geometry: Some(Geometry::of::<SETS, WAYS, LINE_SIZE>()),
+                measured_miss_rate: None,
+            },
+            |mut simulation_result, event| {
+                let instruction = match event {
+                    TraceEvent::Instruction(instruction) => instruction,
+                    TraceEvent::FlushLine(address) => {
+                        lru_cache.invalidate(address);
+                        if let Some(classifier) = &mut classifier {
+                            classifier.invalidate(address / LINE_SIZE);
+                        }
+                        return simulation_result;
+                    }
+                    TraceEvent::Client(client) => {
+                        lru_cache.set_client(client);
+                        return simulation_result;
+                    }
+                    TraceEvent::Load(_) | TraceEvent::Store(_) => return simulation_result,
+                };
+
+                // check all cache-lines the instruction spans, each of them exactly once
+                // if we just check the start address of the instruction
+                // we would fail to consider the case where an instruction spans multiple cache-blocks
+                // this happens for variable size instruction sets (x86, Arm thumb)
+                let mut access = MemoryAccess {
+                    address: instruction.address,
+                    hit: true,
+                    miss_kind: None,
+                    evicted: None,
+                    line_crossing: false,
+                    timestamp: None,
+                };
+                for (i, (address, length)) in line_chunks::<LINE_SIZE>(&instruction).enumerate() {
+                    if i == 1 {
+                        simulation_result.line_crossings += 1;
+                        access.line_crossing = true;
+                    }
+                    touched.touch(address, length);
+
+                    let cache_hit = lru_cache.get_bytes(address, length);
+                    let miss_kind = classifier
+                        .as_mut()
+                        .and_then(|classifier| classifier.classify(address / LINE_SIZE, cache_hit));
+
+                    // the first cache-line that missed explains the miss of the instruction
+                    if let CacheHit::Miss { prev } = cache_hit {
+                        if access.hit {
+                            access.miss_kind = miss_kind;
+                            access.evicted = prev;
+                        }
+                        access.hit = false;
+                    }
+                }
+
+                simulation_result.hits.push(access.hit);
+                if access.hit {
+                    simulation_result.hit_count += 1;
+                    hook(&instruction, CacheHit::Hit);
+                } else {
+                    simulation_result.miss_count += 1;
+                    hook(
+                        &instruction,
+                        CacheHit::Miss {
+                            prev: access.evicted,
+                        },
+                    );
+                }
+
+                if let Some(memory_accesses) = &mut simulation_result.memory_accesses {
+                    memory_accesses.push(access);
+                }
+
+                simulation_result
+            },
+        );
+
+        simulation_result.bytes_fetched = lru_cache.bytes_fetched() - bytes_fetched;
+        simulation_result.bytes_used = lru_cache.bytes_used() - bytes_used;
+        simulation_result.polluting_fills = lru_cache.polluting_fills() - polluting_fills;
+        touched.count_into(&mut simulation_result);
+        simulation_result
+    }
+
+    /// simulates only the `compare` block called `name`
+    pub fn simulate_named<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        file_data: &str,
+        name: &str,
+        log_memory_accesses: bool,
+    ) -> Result<Self, SimulationError> {
+        let trace_file = Self::parse(file_data, false)?;
+
+        let Some(block) = trace_file.events_named(name) else {
+            return Err(SimulationError::UnknownTrace(name.to_string()));
+        };
+
+        lru_cache.reset();
+        Self::warm_up(lru_cache, trace_file.preamble());
+        let mut simulation_result =
+            Self::simulate_block(lru_cache, name, block, log_memory_accesses);
+        simulation_result.measured_miss_rate = trace_file.measured_miss_rates().get(name).copied();
+
+        // the expected count covers all compare blocks, not only the simulated one
+        if let Some(expected_instructions) = trace_file.expected_instructions() {
+            let instructions = trace_file
+                .into_events(usize::MAX)
+                .flat_map(|(_, block)| block)
+                .filter(|event| matches!(event, TraceEvent::Instruction(_)))
+                .count();
+            Self::check_expected_instructions(Some(expected_instructions), instructions)?;
+        }
+        Ok(simulation_result)
+    }
+
+    /// time-slices the compare blocks `thread_a` and `thread_b` on a shared cache, switching between
+    /// them after every `quantum` trace events until both are done
+    ///
+    /// returns the results of `thread_a`, `thread_b` and both combined as `"thread_a+thread_b"`.
+    /// the `preamble` runs once before the first slice, the distinct addresses and lines of the
+    /// combined result count the bytes touched by both threads once
+    pub fn simulate_interleaved<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        file_data: &str,
+        thread_a: &str,
+        thread_b: &str,
+        quantum: usize,
+    ) -> Result<Vec<Self>, SimulationError> {
+        if quantum == 0 {
+            return Err(SimulationError::ZeroQuantum);
+        }
+
+        let trace_file = Self::parse(file_data, false)?;
+        let events = |name: &str| {
+            trace_file
+                .events_named(name)
+                .map(Vec::from_iter)
+                .ok_or_else(|| SimulationError::UnknownTrace(name.to_string()))
+        };
+        let events = [events(thread_a)?, events(thread_b)?];
+        let mut slices = events.each_ref().map(|events| events.chunks(quantum));
+
+        lru_cache.reset();
+        Self::warm_up(lru_cache, trace_file.preamble());
+        let mut results = [thread_a, thread_b].map(|name| Simulation {
+            name: name.to_string(),
+            geometry: Some(Geometry::of::<SETS, WAYS, LINE_SIZE>()),
+            ..Default::default()
+        });
+        let mut touched = [TouchedBytes::default(), TouchedBytes::default()];
+        loop {
+            let mut switched = false;
+            for ((slices, result), touched) in slices.iter_mut().zip(&mut results).zip(&mut touched)
+            {
+                if let Some(slice) = slices.next() {
+                    *result += Self::simulate_slice(
+                        lru_cache,
+                        &result.name,
+                        slice.to_vec(),
+                        false,
+                        &mut |_, _| {},
+                        touched,
+                    );
+                    switched = true;
+                }
+            }
+            if !switched {
+                break;
+            }
+        }
+        for (result, touched) in results.iter_mut().zip(&touched) {
+            touched.count_into(result);
+        }
+
+        let mut combined = results[0].clone();
+        combined.name = format!("{thread_a}+{thread_b}");
+        combined += results[1].clone();
+        let [mut touched_a, touched_b] = touched;
+        touched_a.extend(&touched_b);
+        touched_a.count_into(&mut combined);
+
+        let [a, b] = results;
+        let mut simulation_results = vec![a, b, combined];
+        Self::set_measured_miss_rates(trace_file.measured_miss_rates(), &mut simulation_results);
+        Ok(simulation_results)
+    }
+
+    /// simulates an instruction stream that was not generated from a trace file
+    ///
+    /// like in traces the [`Instruction::length`] is given in bits,
+    /// it has to be a positive multiple of 8
+    pub fn simulate_instructions<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        lru_cache: &mut LruCache<SETS, WAYS, LINE_SIZE>,
+        name: &str,
+        instructions: impl IntoIterator<Item = Instruction>,
+        log_memory_accesses: bool,
+    ) -> Result<Self, SimulationError> {
+        let instructions: Vec<Instruction> = instructions.into_iter().collect();
+        if let Some(instruction) = instructions
+            .iter()
+            .find(|instruction| instruction.length == 0 || instruction.length % 8 != 0)
+        {
+            return Err(SimulationError::InvalidInstructionLength(*instruction));
+        }
+
+        lru_cache.reset();
+        Ok(Self::simulate_block(
+            lru_cache,
+            name,
+            instructions,
+            log_memory_accesses,
+        ))
+    }
+
+    /// simulates the instruction fetches with `l1i` and the loads and stores with `l1d`,
+    /// misses of both are looked up in the shared `l2`
+    ///
+    /// the L1 counts are per instruction or data access, the L2 counts per cache-line lookup.
+    /// flushes invalidate the cache-line in all caches, the `preamble` warms up all three caches
+    pub fn simulate_hierarchy<
+        const I_SETS: usize,
+        const I_WAYS: usize,
+        const I_LINE_SIZE: usize,
+        const D_SETS: usize,
+        const D_WAYS: usize,
+        const D_LINE_SIZE: usize,
+        const L2_SETS: usize,
+        const L2_WAYS: usize,
+        const L2_LINE_SIZE: usize,
+    >(
+        l1i: &mut LruCache<I_SETS, I_WAYS, I_LINE_SIZE>,
+        l1d: &mut LruCache<D_SETS, D_WAYS, D_LINE_SIZE>,
+        l2: &mut LruCache<L2_SETS, L2_WAYS, L2_LINE_SIZE>,
+        file_data: &str,
+    ) -> Result<Vec<HierarchyCounts>, SimulationError> {
+        fn count(counts: &mut LevelCounts, cache_hit: CacheHit) {
+            if cache_hit.is_hit() {
+                counts.hits += 1;
+            } else {
+                counts.misses += 1;
+            }
+        }
+
+        let trace_file = Self::parse(file_data, false)?;
+        let preamble = trace_file.preamble();
+
+        let results = trace_file
+            .into_events(usize::MAX)
+            .map(|(name, block)| {
+                l1i.reset();
+                l1d.reset();
+                l2.reset();
+
+                let mut counts = HierarchyCounts {
+                    name: name.to_string(),
+                    ..Default::default()
+                };
+                // the counts of the preamble are dropped, only the cache state is kept
+                let mut warm_up = HierarchyCounts::default();
+                let events = (preamble
+                    .clone()
+                    .into_iter()
+                    .flatten()
+                    .map(|event| (false, event)))
+                .chain(block.map(|event| (true, event)));
+                for (counted, event) in events {
+                    let counts = if counted { &mut counts } else { &mut warm_up };
+                    match event {
+                        TraceEvent::Instruction(instruction) => {
+                            let mut hit = true;
+                            for (address, length) in line_chunks::<I_LINE_SIZE>(&instruction) {
+                                if l1i.get_bytes(address, length).is_hit() {
+                                    continue;
+                                }
+
+                                hit = false;
+                                // the L1 line may span multiple L2 lines
+                                let line = Instruction {
+                                    address,
+                                    length: length * 8,
+                                };
+                                for (address, length) in line_chunks::<L2_LINE_SIZE>(&line) {
+                                    count(
+                                        &mut counts.l2_instruction,
+                                        l2.get_bytes(address, length),
+                                    );
+                                }
+                            }
+                            if hit {
+                                counts.l1i.hits += 1;
+                            } else {
+                                counts.l1i.misses += 1;
+                            }
+                        }
+                        TraceEvent::Load(address) | TraceEvent::Store(address) => {
+                            let cache_hit = l1d.get(address);
+                            count(&mut counts.l1d, cache_hit);
+                            if cache_hit.is_miss() {
+                                count(&mut counts.l2_data, l2.get(address));
+                            }
+                        }
+                        TraceEvent::FlushLine(address) => {
+                            l1i.invalidate(address);
+                            l1d.invalidate(address);
+                            l2.invalidate(address);
+                        }
+                        TraceEvent::Client(client) => {
+                            l1i.set_client(client);
+                            l1d.set_client(client);
+                            l2.set_client(client);
+                        }
+                    }
+                }
+
+                counts
+            })
+            .collect();
+
+        Ok(results)
+    }
+
+    /// the line size out of 16, 32, 64 and 128 bytes with the lowest total time of all traces
+    /// for a 4-way set-associative cache of `total_size` bytes, the smaller line size wins ties
+    ///
+    /// larger lines profit from spatial locality but fetch more unused bytes and hold fewer lines,
+    /// `total_size` has to be a power of two from 1KB to 64KB
+    pub fn best_line_size_4_way(
+        file_data: &str,
+        total_size: usize,
+        params: &Params,
+    ) -> Result<usize, SimulationError> {
+        const WAYS: usize = 4;
+
+        fn total_time<
+            const CLOCK_SPEED_MHZ: u32,
+            const SETS: usize,
+            const WAYS: usize,
+            const LINE_SIZE: usize,
+        >(
+            file_data: &str,
+            params: &Params,
+        ) -> Result<f64, SimulationError> {
+            let mut lru_cache = LruCache::<SETS, WAYS, LINE_SIZE>::new();
+            let simulation_results =
+                Simulation::<CLOCK_SPEED_MHZ>::simulate(&mut lru_cache, file_data, false)?;
+
+            Ok(simulation_results
+                .iter()
+                .map(|result| result.total_time_us(params))
+                .sum())
+        }
+
+        type TotalTime = fn(&str, &Params) -> Result<f64, SimulationError>;
+
+        // the number of sets is a const generic, so every size needs its own instantiation
+        macro_rules! line_sizes {
+            ($total_size:literal; $($line_size:literal),+) => {
+                [$((
+                    $line_size,
+                    total_time::<CLOCK_SPEED_MHZ, { $total_size / (WAYS * $line_size) }, WAYS, $line_size>
+                        as TotalTime,
+                )),+]
+            };
+        }
+        macro_rules! candidates {
+            ($($total_size:literal),+) => {
+                match total_size {
+                    $($total_size => line_sizes!($total_size; 16, 32, 64, 128),)+
+                    _ => return Err(SimulationError::UnsupportedCacheSize(total_size)),
+                }
+            };
+        }
+        let candidates = candidates!(1024, 2048, 4096, 8192, 16384, 32768, 65536);
+
+        let mut best: Option<(usize, f64)> = None;
+        for (line_size, total_time) in candidates {
+            let time = total_time(file_data, params)?;
+            if best.is_none_or(|(_, best_time)| time < best_time) {
+                best = Some((line_size, time));
+            }
+        }
+
+        best.map(|(line_size, _)| line_size)
+            .ok_or(SimulationError::UnsupportedCacheSize(total_size))
+    }
+
+    /// the smallest number of ways out of 1, 2, 3, 4, 8 and 16 for which no trace has conflict misses
+    /// with `SETS` sets of `LINE_SIZE` bytes, or `None` if even 16 ways are not enough
+    ///
+    /// see [`MissKind::Conflict`]
+    pub fn min_ways_for_zero_conflicts<const SETS: usize, const LINE_SIZE: usize>(
+        file_data: &str,
+    ) -> Result<Option<usize>, SimulationError> {
+        fn conflict_misses<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+            file_data: &str,
+        ) -> Result<usize, SimulationError> {
+            let mut lru_cache = LruCache::<SETS, WAYS, LINE_SIZE>::new();
+            let simulation_results = Simulation::<0>::simulate(&mut lru_cache, file_data, true)?;
+
+            Ok(simulation_results
+                .iter()
+                .flat_map(|result| result.memory_accesses.iter().flatten())
+                .filter(|access| access.miss_kind == Some(MissKind::Conflict))
+                .count())
+        }
+
+        type ConflictMisses = fn(&str) -> Result<usize, SimulationError>;
+        let candidates: [(usize, ConflictMisses); 6] = [
+            (1, conflict_misses::<SETS, 1, LINE_SIZE>),
+            (2, conflict_misses::<SETS, 2, LINE_SIZE>),
+            (3, conflict_misses::<SETS, 3, LINE_SIZE>),
+            (4, conflict_misses::<SETS, 4, LINE_SIZE>),
+            (8, conflict_misses::<SETS, 8, LINE_SIZE>),
+            (16, conflict_misses::<SETS, 16, LINE_SIZE>),
+        ];
+
+        for (ways, conflict_misses) in candidates {
+            if conflict_misses(file_data)? == 0 {
+                return Ok(Some(ways));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// simulates every compare block with Belady's optimal replacement policy for the given geometry
+    ///
+    /// on a miss in a full set the line whose next use lies furthest in the future is evicted,
+    /// which gives a lower bound for the miss count any replacement policy can achieve.
+    /// the complete instruction stream of a block is buffered, only hit and miss counts are recorded.
+    /// the `preamble` is ignored, every block starts with an empty cache
+    pub fn simulate_opt<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        file_data: &str,
+    ) -> Result<Vec<Self>, SimulationError> {
+        let trace_file = Self::parse(file_data, false)?;
+        let expected_instructions = trace_file.expected_instructions();
+        let measured_miss_rates = trace_file.measured_miss_rates().clone();
+
+        let mut simulation_results: Vec<Self> = trace_file
+            .into_iter()
+            .map(|(name, block)| Self::simulate_block_opt::<SETS, WAYS, LINE_SIZE>(name, block))
+            .collect();
+
+        Self::check_expected_instructions(
+            expected_instructions,
+            Self::instruction_count(&simulation_results),
+        )?;
+        Self::set_measured_miss_rates(&measured_miss_rates, &mut simulation_results);
+        Ok(simulation_results)
+    }
+
+    fn simulate_block_opt<const SETS: usize, const WAYS: usize, const LINE_SIZE: usize>(
+        name: &str,
+        instructions: impl IntoIterator<Item = Instruction>,
+    ) -> Self {
+        // (instruction index, cache-line) for every cache-line access in order
+        let accesses: Vec<(usize, usize)> = instructions
+            .into_iter()
+            .enumerate()
+            .flat_map(|(index, instruction)| {
+                line_chunks::<LINE_SIZE>(&instruction)
+                    .map(move |(address, _)| (index, address / LINE_SIZE))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        // position of the next access to the same cache-line
+        let mut next_use = vec![usize::MAX; accesses.len()];
+        let mut upcoming = HashMap::new();
+        for (position, &(_, line)) in accesses.iter().enumerate().rev() {
+            if let Some(next) = upcoming.insert(line, position) {
+                next_use[position] = next;
+            }
+        }
+
+        // resident cache-lines of each set with the position of their next use
+        let mut sets: Vec<Vec<(usize, usize)>> = vec![Vec::with_capacity(WAYS); SETS];
+        let mut missed = Vec::new();
+        for (position, &(index, line)) in accesses.iter().enumerate() {
+            let set = &mut sets[line % SETS];
+            if let Some(resident) = set.iter_mut().find(|(tag, _)| *tag == line) {
+                resident.1 = next_use[position];
+                continue;
+            }
+
+            if set.len() == WAYS {
+                let furthest = (0..WAYS).max_by_key(|&way| set[way].1).unwrap();
+                set.swap_remove(furthest);
+            }
+            set.push((line, next_use[position]));
+
+            if missed.last() != Some(&index) {
+                missed.push(index);
+            }
+        }
+
+        let instruction_count = accesses.last().map_or(0, |&(index, _)| index + 1);
+        let mut crossing = accesses
+            .windows(2)
+            .filter(|pair| pair[0].0 == pair[1].0)
+            .map(|pair| pair[0].0)
+            .collect::<Vec<_>>();
+        crossing.dedup();
+        let mut misses = missed.iter().peekable();
+        let hits = (0..instruction_count)
+            .map(|index| misses.next_if_eq(&&index).is_none())
+            .collect();
+        Simulation {
+            name: name.to_string(),
+            hit_count: (instruction_count - missed.len()) as u32,
+            miss_count: missed.len() as u32,
+            bytes_fetched: 0,
+            bytes_used: 0,
+            line_crossings: crossing.len() as u32,
+            polluting_fills: 0,
+            distinct_addresses: 0,
+            distinct_lines: 0,
+            hits,
+            memory_accesses: None,
+            geometry: Some(Geometry::of::<SETS, WAYS, LINE_SIZE>()),
+            measured_miss_rate: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn geometry(&self) -> Option<Geometry> {
+        self.geometry
+    }
+
+    /// clears the counters and logged memory accesses but keeps the name and allocations
+    pub fn reset_stats(&mut self) {
+        self.hit_count = 0;
+        self.miss_count = 0;
+        self.bytes_fetched = 0;
+        self.bytes_used = 0;
+        self.line_crossings = 0;
+        self.polluting_fills = 0;
+        self.distinct_addresses = 0;
+        self.distinct_lines = 0;
+        self.hits.clear();
+        if let Some(memory_accesses) = &mut self.memory_accesses {
+            memory_accesses.clear();
+        }
+    }
+
+    fn percent_hit(&self) -> f64 {
+        100.0 * f64::from(self.hit_count) / (f64::from(self.hit_count) + f64::from(self.miss_count))
+    }
+
+    fn percent_miss(&self) -> f64 {
+        100.0 * f64::from(self.miss_count)
+            / (f64::from(self.hit_count) + f64::from(self.miss_count))
+    }
+
+    /// percentage of the bytes loaded into the cache that were actually accessed
+    fn fetch_efficiency(&self) -> f64 {
+        100.0 * self.bytes_used as f64 / self.bytes_fetched as f64
+    }
+
+    /// only the hits not overlapping a miss with [`CacheModel::HitUnderMiss`] and no MSHR limit
+    fn hit_time_us(&self, params: &Params) -> f64 {
+        let cycle_time_us = f64::from(CLOCK_SPEED_MHZ).recip();
+        let hit_cycles = u64::from(self.hit_count) * u64::from(params.cycles_hit);
+        let overlapped_cycles = match params.cache_model {
+            // with an MSHR limit the fills already overlap everything, only stalls count as miss time
+            CacheModel::Blocking => 0,
+            CacheModel::HitUnderMiss if params.mshr_count.is_some() => 0,
+            CacheModel::HitUnderMiss => hit_cycles.min(
+                u64::from(self.miss_count)
+                    * u64::from(params.cycles_miss.saturating_sub(params.cycles_hit)),
+            ),
+        };
+
+        (hit_cycles - overlapped_cycles) as f64 * cycle_time_us
+    }
+
+    /// includes the [`Contention`] latency if configured
+    ///
+    /// with an MSHR limit a miss takes `cycles_hit` cycles plus the [`Simulation::stall_cycles`]
+    fn miss_time_us(&self, params: &Params) -> f64 {
+        let cycle_time_us = f64::from(CLOCK_SPEED_MHZ).recip();
+        let base_cycles = match self.stall_cycles(params) {
+            Some(stall_cycles) => {
+                f64::from(self.miss_count) * f64::from(params.cycles_hit) + stall_cycles as f64
+            }
+            None => f64::from(self.miss_count) * f64::from(params.cycles_miss),
+        };
+
+        let contention_cycles: f64 = contention_cycles(&self.hits, params).sum();
+
+        (base_cycles + contention_cycles) * cycle_time_us
+    }
+
+    /// includes the [`Params::line_cross_penalty`] of instructions spanning multiple cache-lines
+    fn total_time_us(&self, params: &Params) -> f64 {
+        let cycle_time_us = f64::from(CLOCK_SPEED_MHZ).recip();
+        let line_cross_time_us =
+            f64::from(self.line_crossings) * f64::from(params.line_cross_penalty) * cycle_time_us;
+
+        self.hit_time_us(params) + self.miss_time_us(params) + line_cross_time_us
+    }
+
+    /// instructions per cycle of a pipeline retiring one instruction per cycle,
+    /// every miss stalls it for `cycles_miss - cycles_hit` cycles
+    pub fn estimate_ipc(&self, params: &Params) -> f64 {
+        let instructions = self.hit_count + self.miss_count;
+        let stall_cycles = u64::from(self.miss_count)
+            * u64::from(params.cycles_miss.saturating_sub(params.cycles_hit));
+
+        f64::from(instructions) / (u64::from(instructions) + stall_cycles) as f64
+    }
+
+    /// per set the number of distinct tags accessed, sets with more tags than ways are conflict-prone
+    ///
+    /// only the start address of each instruction is considered.
+    /// `None` if the memory accesses were not logged
+    pub fn conflict_matrix<const SETS: usize, const LINE_SIZE: usize>(&self) -> Option<Vec<usize>> {
+        let memory_accesses = self.memory_accesses.as_ref()?;
+
+        let mut tags = vec![HashSet::new(); SETS];
+        for access in memory_accesses {
+            let line = access.address / LINE_SIZE;
+            tags[line % SETS].insert(line / SETS);
+        }
+
+        Some(tags.iter().map(HashSet::len).collect())
+    }
+
+    /// cycles the front end stalls because all `params.mshr_count` MSHRs hold outstanding fills
+    ///
+    /// with an MSHR limit the front end doesn't block on a miss: every instruction is issued after
+    /// `cycles_hit` cycles and a miss occupies an MSHR for `cycles_miss` cycles.
+    /// a miss without a free MSHR waits for the earliest fill, the last instruction waits for all
+    /// outstanding fills. the stalls are part of the miss time.
+    /// `None` if no MSHR limit is set
+    pub fn stall_cycles(&self, params: &Params) -> Option<u64> {
+        Some(mshr_stalls(&self.hits, params)?.sum())
+    }
+
+    /// the logged memory accesses as Chrome tracing JSON for `chrome://tracing`,
+    /// one duration event per instruction with the category `hit` or `miss`
+    ///
+    /// the events are placed by the [`MemoryAccess::timestamp`]s.
+    /// `None` if the memory accesses were not logged by [`Simulation::simulate_timed`]
+    pub fn to_chrome_trace(&self) -> Option<String> {
+        let memory_accesses = self.memory_accesses.as_ref()?;
+        let cycle_time_us = f64::from(CLOCK_SPEED_MHZ).recip();
+
+        let mut start = 0;
+        let events: Vec<String> = memory_accesses
+            .iter()
+            .map(|access| {
+                let timestamp = access.timestamp?;
+                let category = if access.hit { "hit" } else { "miss" };
+                let event = format!(
+                    "{{\"name\":\"{:#X}\",\"cat\":\"{category}\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":0,\"tid\":0}}",
+                    access.address,
+                    start as f64 * cycle_time_us,
+                    (timestamp - start) as f64 * cycle_time_us
+                );
+                start = timestamp;
+                Some(event)
+            })
+            .collect::<Option<_>>()?;
+
+        Some(format!("{{\"traceEvents\":[{}]}}", events.join(",")))
+    }
+
+    pub fn report(&self, params: &Params) -> SimulationReport {
+        SimulationReport {
+            name: self.name.clone(),
+            hit_count: self.hit_count,
+            miss_count: self.miss_count,
+            percent_hit: self.percent_hit(),
+            percent_miss: self.percent_miss(),
+            bytes_used: self.bytes_used,
+            bytes_fetched: self.bytes_fetched,
+            fetch_efficiency: self.fetch_efficiency(),
+            polluting_fills: self.polluting_fills,
+            distinct_addresses: self.distinct_addresses,
+            distinct_lines: self.distinct_lines,
+            clock_speed_mhz: CLOCK_SPEED_MHZ,
+            cycles_hit: params.cycles_hit,
+            cycles_miss: params.cycles_miss,
+            hit_time_us: self.hit_time_us(params),
+            miss_time_us: self.miss_time_us(params),
+            total_time_us: self.total_time_us(params),
+            ipc: self.estimate_ipc(params),
+            relative_time_pct: None,
+            measured_miss_rate: self.measured_miss_rate,
+            miss_rate_error: self
+                .measured_miss_rate
+                .map(|measured| (self.percent_miss() / 100.0 - measured).abs()),
+            stall_cycles: self.stall_cycles(params),
+            precision: params.precision,
+        }
+    }
+
+    pub fn format_summary(&self, params: &Params) -> String {
+        self.report(params).to_string()
+    }
+
+    pub fn memory_accesses(simulation_results: &[Self]) -> String {
+        simulation_results
+            .iter()
+            .filter_map(|sim| {
+                let memory_accesses = sim.memory_accesses.as_ref()?;
+                Some(
+                    std::iter::once(format!("{}:\n", sim.name))
+                        .chain(
+                            memory_accesses.iter().map(|access| {
+                                format!("{:X}: hit={:?}\n", access.address, access.hit)
+                            }),
+                        )
+                        .collect::<String>(),
+                )
+            })
+            .collect::<String>()
+    }
+
+    /// logged memory accesses annotated with the kind of miss and the evicted address,
+    /// e.g. `0x1000 Miss(conflict) evicted=0x2000`
+    pub fn format_explained(&self) -> String {
+        self.format_explained_with(Radix::Hex)
+    }
+
+    /// like [`Simulation::format_explained`] with the addresses printed in `radix`
+    pub fn format_explained_with(&self, radix: Radix) -> String {
+        let Some(memory_accesses) = &self.memory_accesses else {
+            return String::new();
+        };
+
+        memory_accesses
+            .iter()
+            .map(
+                |access| match (access.hit, access.miss_kind, access.evicted) {
+                    (true, _, _) => format!("{} Hit", radix.format(access.address)),
+                    (false, miss_kind, evicted) => {
+                        let mut line = format!("{} Miss", radix.format(access.address));
+                        if let Some(miss_kind) = miss_kind {
+                            line.push_str(&format!("({miss_kind})"));
+                        }
+                        if let Some(evicted) = evicted {
+                            line.push_str(&format!(" evicted={}", radix.format(evicted)));
+                        }
+                        line
+                    }
+                },
+            )
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// reports in the [`Params::sort_order`], the relative time is always relative to the fastest trace
+    pub fn reports(simulation_results: &[Self], config: Params) -> Vec<SimulationReport> {
+        let mut reports = simulation_results
+            .iter()
+            .map(|r| r.report(&config))
+            .collect::<Vec<_>>();
+
+        let Some(baseline) = reports
+            .iter()
+            .map(|r| r.total_time_us)
+            .min_by(|a, b| a.partial_cmp(b).unwrap())
+        else {
+            return reports;
+        };
+
+        for report in &mut reports {
+            report.relative_time_pct = Some((report.total_time_us - baseline) / baseline * 100.0);
+        }
+
+        match config.sort_order {
+            SortOrder::ByTime => {
+                reports.sort_by(|a, b| a.total_time_us.partial_cmp(&b.total_time_us).unwrap())
+            }
+            SortOrder::ByName => reports.sort_by(|a, b| a.name.cmp(&b.name)),
+            SortOrder::Source => {}
+        }
+
+        reports
+    }
+
+    /// traces whose total time grew by more than `threshold_pct` percent from `baseline` to `candidate`
+    /// and traces only present in one of them, sorted by name
+    pub fn diff(
+        baseline: &[Self],
+        candidate: &[Self],
+        params: &Params,
+        threshold_pct: f64,
+    ) -> Vec<Regression> {
+        let total_times = |results: &[Self]| -> BTreeMap<String, f64> {
+            results
+                .iter()
+                .map(|result| (result.name.clone(), result.total_time_us(params)))
+                .collect()
+        };
+        let baseline = total_times(baseline);
+        let mut candidate = total_times(candidate);
+
+        let mut regressions = Vec::new();
+        for (name, baseline_time_us) in baseline {
+            let candidate_time_us = candidate.remove(&name);
+            let regressed = candidate_time_us.is_none_or(|candidate_time_us| {
+                (candidate_time_us - baseline_time_us) / baseline_time_us * 100.0 > threshold_pct
+            });
+            if regressed {
+                regressions.push(Regression {
+                    name,
+                    baseline_time_us: Some(baseline_time_us),
+                    candidate_time_us,
+                });
+            }
+        }
+        regressions.extend(
+            candidate
+                .into_iter()
+                .map(|(name, candidate_time_us)| Regression {
+                    name,
+                    baseline_time_us: None,
+                    candidate_time_us: Some(candidate_time_us),
+                }),
+        );
+        regressions.sort_by(|a, b| a.name.cmp(&b.name));
+
+        regressions
+    }
+
+    /// fails if the results were simulated with different cache geometries
+    pub fn compare(simulation_results: &[Self], config: Params) -> Result<String, SimulationError> {
+        Self::check_geometry(simulation_results)?;
+
+        let mut output = Vec::new();
+        Self::write_compare(simulation_results, config, &mut output)
+            .expect("writing to a Vec<u8> never fails");
+        Ok(String::from_utf8(output).expect("reports are valid utf-8"))
+    }
+
+    /// like [`Simulation::compare`] but writes each report directly to `writer`,
+    /// a geometry mismatch is returned as an [`io::ErrorKind::InvalidInput`] error
+    pub fn write_compare<W: Write>(
+        simulation_results: &[Self],
+        config: Params,
+        writer: &mut W,
+    ) -> io::Result<()> {
+        Self::check_geometry(simulation_results)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        let reports = Self::reports(simulation_results, config);
+        if reports.is_empty() {
+            return write!(writer, "nothing to compare");
+        }
+
+        for (i, report) in reports.iter().enumerate() {
+            if i > 0 {
+                writeln!(writer)?;
+            }
+            write!(writer, "{report}")?;
+        }
+        Ok(())
+    }
+}
+
+/// the ways simulating a trace file can fail
+#[derive(Debug)]
+pub enum SimulationError {
+    Io {
+        context: &'static str,
+        source: io::Error,
+    },
+    Parse(TraceParseError<'static>),
+    /// the file defines blocks but none of them is a `compare` block
+    NoComparableTraces,
+    /// the file defines no blocks at all
+    Empty,
+    /// a compare block contains a `loop(inf)` and no instruction limit was given
+    UnboundedLoop(String),
+    /// the simulated instructions differ from the `// @expect_instructions` count
+    InstructionCountMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// no compare block with this name exists
+    UnknownTrace(String),
+    /// relocating the trace moves an address past `usize::MAX`
+    AddressOverflow {
+        trace: String,
+        offset: usize,
+    },
+    /// results of different cache geometries were compared, `(trace name, geometry)` of the first
+    /// result and of the first one that differs from it
+    GeometryMismatch {
+        expected: (String, Geometry),
+        actual: (String, Geometry),
+    },
+    /// no cache geometries are available for this total size in bytes
+    UnsupportedCacheSize(usize),
+    /// an instruction given to [`Simulation::simulate_instructions`] is not a positive multiple
+    /// of 8 bits long
+    InvalidInstructionLength(Instruction),
+    /// [`Simulation::simulate_interleaved`] was called with a `quantum` of 0
+    ZeroQuantum,
+    /// [`Simulation::simulate_monte_carlo`] was called with 0 `runs`
+    ZeroRuns,
+}
+
+impl std::fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimulationError::Io { context, source } => write!(f, "{context}: {source}"),
+            SimulationError::Parse(e) => write!(f, "failed to parse access trace file: {e}"),
+            SimulationError::NoComparableTraces => write!(f, "the file contains no compare blocks"),
+            SimulationError::Empty => write!(f, "the file contains no blocks"),
+            SimulationError::UnboundedLoop(name) => write!(
+                f,
+                "trace '{name}' contains 'loop(inf)' and needs an instruction limit"
+            ),
+            SimulationError::InstructionCountMismatch { expected, actual } => write!(
+                f,
+                "expected {expected} instructions but the traces have {actual}"
+            ),
+            SimulationError::UnknownTrace(name) => write!(f, "no compare block named '{name}'"),
+            SimulationError::AddressOverflow { trace, offset } => write!(
+                f,
+                "relocating trace '{trace}' by {offset:#X} overflows the address space"
+            ),
+            SimulationError::UnsupportedCacheSize(total_size) => write!(
+                f,
+                "unsupported cache size of {total_size}B, expected a power of two from 1KB to 64KB"
+            ),
+            SimulationError::GeometryMismatch { expected, actual } => write!(
+                f,
+                "trace '{}' was simulated with {} but trace '{}' with {}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            SimulationError::InvalidInstructionLength(instruction) => write!(
+                f,
+                "instruction at {:#X} is {} bits long, expected a positive multiple of 8",
+                instruction.address, instruction.length
+            ),
+            SimulationError::ZeroQuantum => write!(f, "the time slice quantum must be nonzero"),
+            SimulationError::ZeroRuns => {
+                write!(f, "a monte carlo simulation needs at least one run")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SimulationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SimulationError::Io { source, .. } => Some(source),
+            SimulationError::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// miss rate of a compare block over multiple seeds, see [`Simulation::simulate_monte_carlo`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonteCarloResult {
+    pub name: String,
+    pub runs: usize,
+    pub mean_miss_rate: f64,
+    /// 95% confidence interval of the mean miss rate
+    pub confidence_interval: (f64, f64),
+}
+
+impl MonteCarloResult {
+    fn new(name: String, miss_rates: &[f64]) -> Self {
+        let runs = miss_rates.len();
+        let mean = miss_rates.iter().sum::<f64>() / runs as f64;
+
+        // normal approximation with the sample standard deviation, a single run has no spread
+        let half_width = if runs > 1 {
+            let variance = miss_rates
+                .iter()
+                .map(|miss_rate| (miss_rate - mean).powi(2))
+                .sum::<f64>()
+                / (runs - 1) as f64;
+            1.96 * (variance / runs as f64).sqrt()
+        } else {
+            0.0
+        };
+
+        Self {
+            name,
+            runs,
+            mean_miss_rate: mean,
+            confidence_interval: (mean - half_width, mean + half_width),
+        }
+    }
+}
+
+/// a trace flagged by [`Simulation::diff`], the total time is `None` for the set missing the trace
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regression {
+    pub name: String,
+    pub baseline_time_us: Option<f64>,
+    pub candidate_time_us: Option<f64>,
+}
+
+/// hits and misses of a single cache level
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct LevelCounts {
+    pub hits: u32,
+    pub misses: u32,
+}
+
+/// per compare block the counts of [`Simulation::simulate_hierarchy`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HierarchyCounts {
+    pub name: String,
+    pub l1i: LevelCounts,
+    pub l1d: LevelCounts,
+    /// L2 lookups caused by instruction fetches
+    pub l2_instruction: LevelCounts,
+    /// L2 lookups caused by loads and stores
+    pub l2_data: LevelCounts,
+}
+
+/// see [`Simulation::simulate_split_report`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct SplitCounts {
+    pub full_hit: u32,
+    pub full_miss: u32,
+    pub split: u32,
+}
+
+/// everything reported about a single simulated trace
+///
+/// the cli and the wasm output are both rendered from this struct,
+/// either via [`Display`](std::fmt::Display) or [`SimulationReport::to_json`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationReport {
+    pub name: String,
+    pub hit_count: u32,
+    pub miss_count: u32,
+    pub percent_hit: f64,
+    pub percent_miss: f64,
+    pub bytes_used: u64,
+    pub bytes_fetched: u64,
+    pub fetch_efficiency: f64,
+    /// cache-lines evicted without a single hit, see [`LruCache::polluting_fills`]
+    pub polluting_fills: u64,
+    pub distinct_addresses: u64,
+    pub distinct_lines: u64,
+    pub clock_speed_mhz: u32,
+    pub cycles_hit: u32,
+    pub cycles_miss: u32,
+    /// time spent on cache-hits
+    pub hit_time_us: f64,
+    /// time spent on cache-misses
+    pub miss_time_us: f64,
+    pub total_time_us: f64,
+    /// see [`Simulation::estimate_ipc`]
+    pub ipc: f64,
+    /// only set for reports created by [`Simulation::reports`]
+    pub relative_time_pct: Option<f64>,
+    /// see [`TraceFile::measured_miss_rates`]
+    pub measured_miss_rate: Option<f64>,
+    /// absolute difference between the simulated and the measured miss rate
+    pub miss_rate_error: Option<f64>,
+    /// see [`Simulation::stall_cycles`]
+    pub stall_cycles: Option<u64>,
+    /// digits after the decimal point used by the [`std::fmt::Display`] implementation
+    pub precision: usize,
+}
+
+impl SimulationReport {
+    pub fn to_json(&self) -> String {
+        // JSON has no representation for NaN or infinity
+        fn number(value: f64) -> String {
+            if value.is_finite() {
+                value.to_string()
+            } else {
+                String::from("null")
+            }
+        }
+
+        let fields = [
+            format!(
+                "\"name\":\"{}\"",
+                self.name.replace('\\', "\\\\").replace('"', "\\\"")
+            ),
+            format!("\"hit_count\":{}", self.hit_count),
+            format!("\"miss_count\":{}", self.miss_count),
+            format!("\"percent_hit\":{}", number(self.percent_hit)),
+            format!("\"percent_miss\":{}", number(self.percent_miss)),
+            format!("\"bytes_used\":{}", self.bytes_used),
+            format!("\"bytes_fetched\":{}", self.bytes_fetched),
+            format!("\"fetch_efficiency\":{}", number(self.fetch_efficiency)),
+            format!("\"polluting_fills\":{}", self.polluting_fills),
+            format!("\"distinct_addresses\":{}", self.distinct_addresses),
+            format!("\"distinct_lines\":{}", self.distinct_lines),
+            format!("\"clock_speed_mhz\":{}", self.clock_speed_mhz),
+            format!("\"cycles_hit\":{}", self.cycles_hit),
+            format!("\"cycles_miss\":{}", self.cycles_miss),
+            format!("\"hit_time_us\":{}", number(self.hit_time_us)),
+            format!("\"miss_time_us\":{}", number(self.miss_time_us)),
+            format!("\"total_time_us\":{}", number(self.total_time_us)),
+            format!("\"ipc\":{}", number(self.ipc)),
+            format!(
+                "\"relative_time_pct\":{}",
+                self.relative_time_pct.map_or(String::from("null"), number)
+            ),
+            format!(
+                "\"measured_miss_rate\":{}",
+                self.measured_miss_rate.map_or(String::from("null"), number)
+            ),
+            format!(
+                "\"miss_rate_error\":{}",
+                self.miss_rate_error.map_or(String::from("null"), number)
+            ),
+            format!(
+                "\"stall_cycles\":{}",
+                self.stall_cycles
+                    .map_or(String::from("null"), |stall_cycles| stall_cycles
+                        .to_string())
+            ),
+        ];
+
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+impl std::fmt::Display for SimulationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let precision = self.precision;
+        let mut result = vec![
+            format!("Trace: {}", self.name),
+            format!(
+                "Number of Instructions: {}",
+                self.hit_count + self.miss_count
+            ),
+            format!("Hits: {}, Misses: {}", self.hit_count, self.miss_count),
+            format!("Percent Hits: {:.precision$}%", self.percent_hit),
+            format!("Percent Misses: {:.precision$}%", self.percent_miss),
+            format!(
+                "Fetch Efficiency: {:.precision$}% ({}B used of {}B fetched)",
+                self.fetch_efficiency, self.bytes_used, self.bytes_fetched
+            ),
+            format!(
+                "Polluting Fills: {} (evicted without a hit)",
+                self.polluting_fills
+            ),
+            format!(
+                "Distinct Addresses: {}, Distinct Lines: {}",
+                self.distinct_addresses, self.distinct_lines
+            ),
+            format!(
+                "Assuming Clock-Speed: {} MHz, Cache-Hit: {} cycles, Cache-Miss: {} cycles",
+                self.clock_speed_mhz, self.cycles_hit, self.cycles_miss
+            ),
+        ];
+
+        let format_time = |time_us: f64| {
+            if time_us >= 1_000_000.0 {
+                format!("{:.precision$}s", time_us / 1_000_000.0)
+            } else if time_us >= 1_000.0 {
+                format!("{:.precision$}ms", time_us / 1_000.0)
+            } else {
+                format!("{:.precision$}us", time_us)
+            }
+        };
+        result.push(format!("Total time: {}", format_time(self.total_time_us)));
+        result.push(format!(
+            "Hit time: {}, Miss time: {}",
+            format_time(self.hit_time_us),
+            format_time(self.miss_time_us)
+        ));
+        result.push(format!("Estimated IPC: {:.precision$}", self.ipc));
+
+        if let Some(stall_cycles) = self.stall_cycles {
+            result.push(format!("MSHR Stalls: {stall_cycles} cycles"));
+        }
+
+        if let (Some(measured), Some(error)) = (self.measured_miss_rate, self.miss_rate_error) {
+            result.push(format!(
+                "Measured Misses: {:.precision$}% (simulation off by {:.precision$} percentage points)",
+                measured * 100.0,
+                error * 100.0
+            ));
+        }
+
+        if let Some(relative_time_pct) = self.relative_time_pct {
+            result.push(format!(
+                "Relative Time: +{relative_time_pct:.precision$}%\n"
+            ));
+        }
+
+        f.write_str(&result.join("\n"))
+    }
+}
+
+/// per instruction the [`Contention`] cycles added to it, 0 for hits or without contention
+fn contention_cycles<'a>(hits: &'a HitSequence, params: &Params) -> impl Iterator<Item = f64> + 'a {
+    let contention = params.contention;
+    let mut window_misses = 0;
+    hits.iter().enumerate().map(move |(i, hit)| {
+        let Some(contention) = contention else {
+            return 0.0;
+        };
+
+        window_misses += usize::from(!hit);
+        if let Some(oldest) = i.checked_sub(contention.window) {
+            window_misses -= usize::from(!hits.get(oldest));
+        }
+
+        if hit {
+            0.0
+        } else {
+            let window_len = (i + 1).min(contention.window);
+            f64::from(contention.cycles_per_miss_rate) * window_misses as f64 / window_len as f64
+        }
+    })
+}
+
+/// per access the cycles it waits for a free MSHR before it is issued,
+/// the last access also waits for the fills still outstanding after it
+///
+/// see [`Simulation::stall_cycles`]
+fn mshr_stalls<'a>(
+    hits: &'a HitSequence,
+    params: &Params,
+) -> Option<impl Iterator<Item = u64> + 'a> {
+    let mshr_count = params.mshr_count?;
+    let cycles_hit = u64::from(params.cycles_hit);
+    let cycles_miss = u64::from(params.cycles_miss);
+
+    // completion times of the outstanding fills, earliest last
+    let mut outstanding: Vec<u64> = Vec::with_capacity(mshr_count);
+    let mut now = 0;
+    let last = hits.len.checked_sub(1);
+    Some(hits.iter().enumerate().map(move |(i, hit)| {
+        let mut stall_cycles = 0;
+        if !hit {
+            outstanding.retain(|&done| done > now);
+            if outstanding.len() == mshr_count {
+                let done = outstanding.pop().unwrap();
+                stall_cycles += done - now;
+                now = done;
+            }
+
+            let done = now + cycles_miss;
+            let position = outstanding.partition_point(|&other| other > done);
+            outstanding.insert(position, done);
+        }
+        now += cycles_hit;
+
+        if Some(i) == last
+            && let Some(&done) = outstanding.first()
+        {
+            stall_cycles += done.saturating_sub(now);
+        }
+        stall_cycles
+    }))
+}
+
+/// splits an instruction into `(address, length)` parts that each lie within a single cache-line
+fn line_chunks<const LINE_SIZE: usize>(
+    instruction: &Instruction,
+) -> impl Iterator<Item = (usize, usize)> {
+    let start = instruction.address;
+    let end = instruction.address + instruction.length / 8;
+
+    (start / LINE_SIZE..end.div_ceil(LINE_SIZE)).map(move |line| {
+        let chunk_start = start.max(line * LINE_SIZE);
+        let chunk_end = end.min((line + 1) * LINE_SIZE);
+        (chunk_start, chunk_end - chunk_start)
+    })
+}
+
+/// accumulates the counts of `rhs` into `self`
+///
+/// the name of `self` is kept, logged memory accesses are appended.
+/// the distinct addresses and lines are summed, addresses touched by both are counted twice
+impl<const CLOCK_SPEED_MHZ: u32> std::ops::AddAssign for Simulation<CLOCK_SPEED_MHZ> {
+    fn add_assign(&mut self, rhs: Self) {
+        self.hit_count += rhs.hit_count;
+        self.miss_count += rhs.miss_count;
+        self.bytes_fetched += rhs.bytes_fetched;
+        self.bytes_used += rhs.bytes_used;
+        self.line_crossings += rhs.line_crossings;
+        self.polluting_fills += rhs.polluting_fills;
+        self.distinct_addresses += rhs.distinct_addresses;
+        self.distinct_lines += rhs.distinct_lines;
+        self.hits.extend(rhs.hits.iter());
+        if let Some(rhs_memory_accesses) = rhs.memory_accesses {
+            self.memory_accesses
+                .get_or_insert_default()
+                .extend(rhs_memory_accesses);
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CacheHit {
+    Hit,
+    Miss { prev: Option<usize> },
+}
+
+impl CacheHit {
+    pub fn is_hit(&self) -> bool {
+        matches!(self, CacheHit::Hit)
+    }
+
+    pub fn is_miss(&self) -> bool {
+        matches!(self, CacheHit::Miss { .. })
+    }
+
+    /// the address stored in the cache-line replaced by a miss
+    pub fn evicted(&self) -> Option<usize> {
+        match self {
+            CacheHit::Hit => None,
+            CacheHit::Miss { prev } => *prev,
+        }
+    }
+
+    /// like the [`Display`](std::fmt::Display) implementation with the evicted address printed in `radix`
+    pub fn format_with(&self, radix: Radix) -> String {
+        match self {
+            CacheHit::Hit => String::from("Hit"),
+            CacheHit::Miss { prev } => match prev {
+                Some(prev) => format!("Miss prev={}", radix.format(*prev)),
+                None => String::from("Miss"),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for CacheHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.format_with(Radix::Hex))
+    }
+}
+
+/// how addresses are printed by [`Simulation::format_explained_with`] and [`CacheHit::format_with`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Radix {
+    /// `0x1000`
+    #[default]
+    Hex,
+    /// `4096`
+    Dec,
+    /// `0b1000000000000`
+    Bin,
+}
+
+impl Radix {
+    pub fn format(self, address: usize) -> String {
+        match self {
+            Radix::Hex => format!("{address:#X}"),
+            Radix::Dec => address.to_string(),
+            Radix::Bin => format!("{address:#b}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        CacheHit, CacheModel, Geometry, LevelCounts, Params, Radix, Regression, Simulation,
+        SimulationError, SortOrder, SplitCounts, line_chunks,
+    };
+    use crate::lru::{InsertionPolicy, LruCache};
+    use crate::trace::{Instruction, TraceFile};
+
+    #[test]
+    fn add_assign_sums_counts() {
+        let sim = |name: &str, hit_count, miss_count| Simulation::<1_600> {
+            name: name.to_string(),
+            hit_count,
+            miss_count,
+            ..Default::default()
+        };
+
+        let mut total = sim("a", 1, 2);
+        total += sim("b", 10, 20);
+        total += sim("c", 100, 200);
+
+        assert_eq!(total.name, "a");
+        assert_eq!(total.hit_count, 111);
+        assert_eq!(total.miss_count, 222);
+    }
+
+    #[test]
+    fn fetch_efficiency_of_large_line() {
+        let mut lru_cache: LruCache<2, 1, 64> = LruCache::new();
+        let results = Simulation::<1_600>::simulate(
+            &mut lru_cache,
+            "compare 'a' {\n    0x0..32..0x4\n}\n",
+            false,
+        )
+        .unwrap();
+        let [result] = results.as_slice() else {
+            panic!("expected exactly one result");
+        };
+
+        assert_eq!(result.bytes_fetched, 64);
+        assert_eq!(result.bytes_used, 4);
+        assert_eq!(result.fetch_efficiency(), 6.25);
+    }
+
+    #[test]
+    fn params_builder_validation() {
+        let params = Params::builder()
+            .cycles_hit(1)
+            .cycles_miss(25)
+            .build()
+            .unwrap();
+        assert_eq!((params.cycles_hit, params.cycles_miss), (1, 25));
+
+        assert!(
+            Params::builder()
+                .cycles_hit(25)
+                .cycles_miss(1)
+                .build()
+                .is_err()
+        );
+        assert!(Params::builder().cycles_miss(25).build().is_err());
+    }
+
+    #[test]
+    fn one_access_per_cache_line() {
+        let chunks = |address, length| {
+            line_chunks::<64>(&Instruction { address, length }).collect::<Vec<_>>()
+        };
+
+        assert_eq!(chunks(0x0, 32), vec![(0x0, 4)]);
+        assert_eq!(chunks(0x3C, 32), vec![(0x3C, 4)]);
+        assert_eq!(chunks(0x3E, 32), vec![(0x3E, 2), (0x40, 2)]);
+    }
+
+    #[test]
+    fn line_granular_accesses_once_per_line() {
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+        let results = Simulation::<1_600>::simulate(
+            &mut lru_cache,
+            "compare 'a' {\n    0x0..32..0x4\n    0x3E..32..0x42\n}\n",
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(results[0].hit_count + results[0].miss_count, 2);
+        // one access for the first instruction, two for the one spanning two lines
+        assert_eq!(lru_cache.access_count(), 3);
+    }
+
+    #[test]
+    fn per_line_and_per_byte_accesses_evict_differently() {
+        // with LRU insertion a new line is the next victim unless it is accessed again,
+        // the further bytes of the same instruction must not count as such an access
+        let trace = "compare 'a' {\n    0x0..32..0x4\n    0x10..32..0x14\n    0x20..32..0x24\n    0x10..32..0x14\n}\n";
+        let (_, block) = TraceFile::try_from(trace)
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+        let instructions: Vec<Instruction> = block.collect();
+        let new_cache = || LruCache::<1, 2, 16>::new().with_insertion_policy(InsertionPolicy::Lru);
+
+        let mut per_byte_cache = new_cache();
+        let mut per_byte_evictions = Vec::new();
+        for instruction in &instructions {
+            for i in 0..instruction.length / 8 {
+                if let CacheHit::Miss { prev: Some(prev) } =
+                    per_byte_cache.get(instruction.address + i)
+                {
+                    per_byte_evictions.push(prev & !0xF);
+                }
+            }
+        }
+
+        let mut per_line_cache = new_cache();
+        let mut per_line_evictions = Vec::new();
+        for instruction in &instructions {
+            for (address, length) in line_chunks::<16>(instruction) {
+                if let CacheHit::Miss { prev: Some(prev) } =
+                    per_line_cache.get_bytes(address, length)
+                {
+                    per_line_evictions.push(prev & !0xF);
+                }
+            }
+        }
+
+        // the second byte promotes 0x10, so 0x20 evicts 0x0 and the second 0x10 hits
+        assert_eq!(per_byte_evictions, [0x0]);
+        // 0x10 stays the victim and gets evicted by 0x20, which in turn is evicted by 0x10
+        assert_eq!(per_line_evictions, [0x10, 0x20]);
+
+        let results = Simulation::<1_600>::simulate(&mut new_cache(), trace, false).unwrap();
+        assert_eq!((results[0].hit_count, results[0].miss_count), (0, 4));
+    }
+
+    #[test]
+    fn compare_and_json_share_reports() {
+        let trace = "compare 'a' {\n    0x0..32..0x100\n}\n\ncompare 'b' {\n    loop(2) {\n        0x0..32..0x100\n    }\n}\n";
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+        let results = Simulation::<1_600>::simulate(&mut lru_cache, trace, false).unwrap();
+        let params = Params::builder()
+            .cycles_hit(1)
+            .cycles_miss(25)
+            .build()
+            .unwrap();
+
+        let reports = Simulation::reports(&results, params);
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].name, "a");
+        assert_eq!(reports[0].relative_time_pct, Some(0.0));
+
+        let text = reports
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(Simulation::compare(&results, params).unwrap(), text);
+        assert!(text.contains("Hits: 60, Misses: 4"));
+        assert!(text.contains("Relative Time: +"));
+
+        let json = reports[1].to_json();
+        assert!(json.starts_with("{\"name\":\"b\","));
+        assert!(json.contains("\"hit_count\":124,\"miss_count\":4,"));
+        assert!(json.ends_with('}'));
+    }
+
+    #[test]
+    fn explain_compulsory_then_conflict() {
+        // both lines map to set 0 of a direct mapped cache with 2 cache-lines
+        let trace = "compare 'a' {\n    0x0..32..0x4\n    0x8..32..0xC\n    0x0..32..0x4\n    0x0..32..0x4\n}\n";
+        let mut lru_cache: LruCache<2, 1, 4> = LruCache::new();
+        let results = Simulation::<1_600>::simulate(&mut lru_cache, trace, true).unwrap();
+
+        assert_eq!(
+            results[0].format_explained(),
+            [
+                "0x0 Miss(compulsory)",
+                "0x8 Miss(compulsory) evicted=0x0",
+                "0x0 Miss(conflict) evicted=0x8",
+                "0x0 Hit",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn opt_beats_lru_on_cyclic_access() {
+        // three cache-lines mapping to the same 2-way set accessed cyclically always miss with LRU
+        let trace = "compare 'a' {\n    loop(4) {\n        0x0..32..0x4\n        0x8..32..0xC\n        0x10..32..0x14\n    }\n}\n";
+
+        let mut lru_cache: LruCache<2, 2, 4> = LruCache::new();
+        let lru = Simulation::<1_600>::simulate(&mut lru_cache, trace, false).unwrap();
+        let opt = Simulation::<1_600>::simulate_opt::<2, 2, 4>(trace).unwrap();
+
+        assert_eq!((lru[0].hit_count, lru[0].miss_count), (0, 12));
+        assert_eq!(opt[0].hit_count + opt[0].miss_count, 12);
+        assert!(opt[0].miss_count < lru[0].miss_count);
+        assert_eq!(opt[0].miss_count, 7);
+    }
+
+    #[test]
+    fn output_precision() {
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+        let results = Simulation::<1_600>::simulate(
+            &mut lru_cache,
+            "compare 'a' {\n    0x0..32..0x100\n}\n",
+            false,
+        )
+        .unwrap();
+        let params = |precision| {
+            Params::builder()
+                .cycles_hit(1)
+                .cycles_miss(25)
+                .precision(precision)
+                .build()
+                .unwrap()
+        };
+
+        let default = Params::builder().cycles_hit(1).cycles_miss(25).build();
+        assert_eq!(default.unwrap().precision, 3);
+        assert!(
+            results[0]
+                .format_summary(&params(3))
+                .contains("Percent Hits: 93.750%")
+        );
+        let summary = results[0].format_summary(&params(6));
+        assert!(summary.contains("Percent Hits: 93.750000%"));
+        assert!(summary.contains("Total time: 0.100000us"));
+    }
+
+    #[test]
+    fn simulate_single_named_trace() {
+        let trace = "compare 'a' {\n    0x0..32..0x4\n}\n\ncompare 'b' {\n    0x0..32..0x8\n}\n\n'c' {\n    0x0..32..0x10\n}\n";
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+
+        let result =
+            Simulation::<1_600>::simulate_named(&mut lru_cache, trace, "b", false).unwrap();
+        assert_eq!(result.name(), "b");
+        assert_eq!((result.hit_count, result.miss_count), (1, 1));
+
+        assert!(Simulation::<1_600>::simulate_named(&mut lru_cache, trace, "c", false).is_err());
+        assert!(Simulation::<1_600>::simulate_named(&mut lru_cache, trace, "d", false).is_err());
+    }
+
+    #[test]
+    fn hit_and_miss_time_sum_to_total() {
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+        let results = Simulation::<1_600>::simulate(
+            &mut lru_cache,
+            "compare 'a' {\n    0x0..32..0x100\n}\n",
+            false,
+        )
+        .unwrap();
+        let params = Params::builder()
+            .cycles_hit(1)
+            .cycles_miss(25)
+            .build()
+            .unwrap();
+
+        let report = results[0].report(&params);
+        assert_eq!(
+            report.hit_time_us + report.miss_time_us,
+            report.total_time_us
+        );
+        assert_eq!(report.miss_time_us, 4.0 * 25.0 / 1_600.0);
+        assert!(
+            Simulation::compare(&results, params)
+                .unwrap()
+                .contains("Hit time: 0.037us, Miss time: 0.062us")
+        );
+    }
+
+    #[test]
+    fn unbounded_loop_needs_instruction_limit() {
+        let trace = "compare 'a' {\n    0x100..32..0x104\n    loop(inf) {\n        0x0..32..0x8\n    }\n}\n";
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+
+        assert!(Simulation::<1_600>::simulate(&mut lru_cache, trace, false).is_err());
+
+        let results =
+            Simulation::<1_600>::simulate_limited(&mut lru_cache, trace, 101, true).unwrap();
+        assert_eq!(results[0].hit_count + results[0].miss_count, 101);
+        assert_eq!(results[0].miss_count, 2);
+    }
+
+    #[test]
+    fn three_ways_remove_three_way_conflict() {
+        // with 8 sets of 4 bytes 0x0, 0x20 and 0x40 all map to set 0
+        let trace = "compare 'a' {\n    loop(4) {\n        0x0..32..0x4\n        0x20..32..0x24\n        0x40..32..0x44\n    }\n}\n";
+
+        assert_eq!(
+            Simulation::<1_600>::min_ways_for_zero_conflicts::<8, 4>(trace).unwrap(),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn parallel_and_sequential_agree() {
+        let trace = "compare 'c' {\n    loop(3) {\n        0x0..32..0x400\n    }\n}\n\ncompare 'a' {\n    0x0..32..0x100\n}\n\ncompare 'b' {\n    0x40..16..0x2000\n}\n";
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+
+        let mut sequential = Simulation::<1_600>::simulate(&mut lru_cache, trace, true).unwrap();
+        sequential.sort_by(|a, b| a.name.cmp(&b.name));
+        let parallel = Simulation::<1_600>::simulate_parallel(&lru_cache, trace, true).unwrap();
+
+        let summary = |results: &[Simulation<1_600>]| {
+            results
+                .iter()
+                .map(|result| {
+                    (
+                        result.name.clone(),
+                        result.hit_count,
+                        result.miss_count,
+                        result.bytes_fetched,
+                        result.bytes_used,
+                        result.format_explained(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(summary(&parallel), summary(&sequential));
+        assert_eq!(
+            parallel.iter().map(Simulation::name).collect::<Vec<_>>(),
+            ["a", "b", "c"]
+        );
+    }
+
+    #[test]
+    fn instruction_split_between_resident_and_missing_line() {
+        // the last instruction starts in the resident line 0x0..0x40 and ends in 0x40..0x80
+        let trace = "compare 'a' {\n    0x0..32..0x8\n    0x3E..32..0x42\n    0x80..32..0x84\n}\n";
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+
+        let split_counts =
+            Simulation::<1_600>::simulate_split_report(&mut lru_cache, trace).unwrap();
+        assert_eq!(
+            split_counts,
+            vec![(
+                String::from("a"),
+                SplitCounts {
+                    full_hit: 1,
+                    full_miss: 2,
+                    split: 1,
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn miss_burst_exceeding_mshrs_stalls() {
+        // every 16th instruction misses
+        let trace = "compare 'a' {\n    0x0..32..0x100\n}\n";
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+        let results = Simulation::<1_600>::simulate(&mut lru_cache, trace, false).unwrap();
+        // 4 misses in a row
+        let trace = "compare 'a' {\n    0x0..32..0x4\n    0x40..32..0x44\n    0x80..32..0x84\n    0xC0..32..0xC4\n}\n";
+        let bursts = Simulation::<1_600>::simulate(&mut lru_cache, trace, false).unwrap();
+        let params = |mshr_count| {
+            Params::builder()
+                .cycles_hit(1)
+                .cycles_miss(25)
+                .mshr_count(mshr_count)
+                .build()
+                .unwrap()
+        };
+
+        // each further miss and the end wait for the previous fill: 25 - 1 cycles
+        assert_eq!(bursts[0].stall_cycles(&params(1)), Some(4 * 24));
+        // the third miss waits for the first fill, the fourth one finds a free MSHR
+        assert_eq!(bursts[0].stall_cycles(&params(2)), Some(23 + 24));
+        assert_eq!(bursts[0].stall_cycles(&params(4)), Some(24));
+        // 15 hits between misses and after the last one hide all but 9 cycles of a fill
+        assert_eq!(results[0].stall_cycles(&params(1)), Some(4 * 9));
+        assert_eq!(results[0].stall_cycles(&params(2)), Some(9));
+
+        let blocking = Params::builder()
+            .cycles_hit(1)
+            .cycles_miss(25)
+            .build()
+            .unwrap();
+        assert_eq!(results[0].stall_cycles(&blocking), None);
+        let total_cycles = |params| results[0].report(&params).total_time_us * 1_600.0;
+        assert!((total_cycles(blocking) - (60.0 + 4.0 * 25.0)).abs() < 1e-9);
+        assert!((total_cycles(params(1)) - (64.0 + 4.0 * 9.0)).abs() < 1e-9);
+        assert!((total_cycles(params(2)) - (64.0 + 9.0)).abs() < 1e-9);
+        // a single MSHR serializes a burst of misses just like a blocking front end
+        let total_cycles = |params| bursts[0].report(&params).total_time_us * 1_600.0;
+        assert!((total_cycles(params(1)) - total_cycles(blocking)).abs() < 1e-9);
+        assert!(total_cycles(params(4)) < total_cycles(params(2)));
+
+        let logged =
+            Simulation::<1_600>::simulate_timed(&mut lru_cache, trace, &params(2)).unwrap();
+        let last = logged[0].memory_accesses.as_ref().unwrap().last().unwrap();
+        assert!((last.timestamp.unwrap() as f64 - total_cycles(params(2))).abs() < 1e-9);
+        assert!(
+            Params::builder()
+                .cycles_hit(1)
+                .cycles_miss(25)
+                .mshr_count(0)
+                .build()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn only_window_contributes() {
+        // 0x1000 maps to the same set as 0x0 and would evict it with a direct-mapped cache
+        let trace =
+            "compare 'a' {\n    0x0..32..0x10\n    0x1000..32..0x1010\n    0x0..32..0x10\n}\n";
+        let mut lru_cache: LruCache<16, 1, 64> = LruCache::new();
+
+        let results = Simulation::<1_600>::simulate(&mut lru_cache, trace, false).unwrap();
+        assert_eq!((results[0].hit_count, results[0].miss_count), (9, 3));
+
+        let results =
+            Simulation::<1_600>::simulate_windowed(&mut lru_cache, trace, 0x0, 0x1000, false)
+                .unwrap();
+        assert_eq!((results[0].hit_count, results[0].miss_count), (7, 1));
+    }
+
+    #[test]
+    fn flushed_line_misses_again() {
+        let trace = "compare 'a' {\n    0x0..32..0x4\n    0x40..32..0x44\n    flush 0x20\n    0x0..32..0x4\n    0x40..32..0x44\n}\n";
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+
+        let results = Simulation::<1_600>::simulate(&mut lru_cache, trace, true).unwrap();
+        assert_eq!(
+            results[0].format_explained(),
+            "0x0 Miss(compulsory)\n0x40 Miss(compulsory)\n0x0 Miss(compulsory)\n0x40 Hit"
+        );
+        assert_eq!(results[0].bytes_fetched, 3 * 64);
+    }
+
+    #[test]
+    fn set_pressure() {
+        // 5 tags in set 0 of a 4-way cache
+        let trace = "compare 'a' {\n    0x0..32..0x4\n    0x8..32..0xC\n    0x10..32..0x14\n    0x18..32..0x1C\n    0x20..32..0x28\n    0x4..32..0x8\n}\n";
+        let mut lru_cache: LruCache<2, 4, 4> = LruCache::new();
+        let results = Simulation::<1_600>::simulate(&mut lru_cache, trace, true).unwrap();
+
+        assert_eq!(results[0].conflict_matrix::<2, 4>(), Some(vec![5, 2]));
+
+        let results = Simulation::<1_600>::simulate(&mut lru_cache, trace, false).unwrap();
+        assert_eq!(results[0].conflict_matrix::<2, 4>(), None);
+    }
+
+    #[test]
+    fn write_compare_matches_compare() {
+        let trace = "compare 'a' {\n    0x0..32..0x100\n}\n\ncompare 'b' {\n    loop(2) {\n        0x0..32..0x100\n    }\n}\n";
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+        let results = Simulation::<1_600>::simulate(&mut lru_cache, trace, false).unwrap();
+        let params = Params::builder()
+            .cycles_hit(1)
+            .cycles_miss(25)
+            .build()
+            .unwrap();
+
+        let mut output = Vec::new();
+        Simulation::write_compare(&results, params, &mut output).unwrap();
+        assert_eq!(
+            output,
+            Simulation::compare(&results, params).unwrap().into_bytes()
+        );
+
+        let mut output = Vec::new();
+        Simulation::<1_600>::write_compare(&[], params, &mut output).unwrap();
+        assert_eq!(output, b"nothing to compare");
+    }
+
+    #[test]
+    fn simulate_hand_built_instructions() {
+        let instructions = [0x0, 0x4, 0x40, 0x0].map(|address| Instruction {
+            address,
+            length: 32,
+        });
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+
+        let result =
+            Simulation::<1_600>::simulate_instructions(&mut lru_cache, "elf", instructions, false)
+                .unwrap();
+        assert_eq!(result.name(), "elf");
+        assert_eq!((result.hit_count, result.miss_count), (2, 2));
+
+        for length in [0, 4, 12] {
+            let instruction = Instruction {
+                address: 0x40,
+                length,
+            };
+            assert!(matches!(
+                Simulation::<1_600>::simulate_instructions(
+                    &mut lru_cache,
+                    "elf",
+                    [instruction],
+                    false
+                ),
+                Err(SimulationError::InvalidInstructionLength(invalid)) if invalid == instruction
+            ));
+        }
+    }
+
+    #[test]
+    fn hit_under_miss_hides_hit_time() {
+        let result = Simulation::<1_000> {
+            hit_count: 100,
+            miss_count: 2,
+            ..Default::default()
+        };
+        let params = |cache_model| {
+            Params::builder()
+                .cycles_hit(1)
+                .cycles_miss(25)
+                .cache_model(cache_model)
+                .build()
+                .unwrap()
+        };
+
+        let blocking = result.report(&params(CacheModel::Blocking));
+        let hit_under_miss = result.report(&params(CacheModel::HitUnderMiss));
+        assert!((blocking.total_time_us - 0.15).abs() < 1e-9);
+        // 2 misses hide 24 hits each
+        assert!((hit_under_miss.total_time_us - 0.102).abs() < 1e-9);
+        assert_eq!(hit_under_miss.miss_time_us, blocking.miss_time_us);
+    }
+
+    #[test]
+    fn reset_stats_keeps_capacity() {
+        let trace = "compare 'a' {\n    0x0..32..0x100\n}\n";
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+        let mut result = Simulation::<1_600>::simulate(&mut lru_cache, trace, true)
+            .unwrap()
+            .remove(0);
+        let capacity = result.memory_accesses.as_ref().unwrap().capacity();
+
+        result.reset_stats();
+        assert_eq!((result.hit_count, result.miss_count), (0, 0));
+        assert_eq!((result.bytes_fetched, result.bytes_used), (0, 0));
+        assert_eq!(result.name(), "a");
+
+        let memory_accesses = result.memory_accesses.as_ref().unwrap();
+        assert!(memory_accesses.is_empty());
+        assert_eq!(memory_accesses.capacity(), capacity);
+    }
+
+    #[test]
+    fn ipc_of_known_split() {
+        let result = Simulation::<1_600> {
+            hit_count: 90,
+            miss_count: 10,
+            ..Default::default()
+        };
+        let params = Params::builder()
+            .cycles_hit(1)
+            .cycles_miss(11)
+            .build()
+            .unwrap();
+
+        // 100 instructions retire in 100 + 10 * 10 cycles
+        assert_eq!(result.estimate_ipc(&params), 0.5);
+        assert!(
+            result
+                .format_summary(&params)
+                .contains("Estimated IPC: 0.500")
+        );
+    }
+
+    #[test]
+    fn relocation_changes_conflicts() {
+        // at offset 0 the loop touches the lines 0, 1 and 3 of a direct-mapped cache with 4 sets,
+        // moved by 8 bytes it touches the lines 0 to 4 and line 4 evicts line 0 every iteration
+        let trace = "compare 'a' {\n    loop(4) {\n        0x0..32..0x20\n        0x30..32..0x40\n    }\n}\n";
+        let mut lru_cache: LruCache<4, 1, 16> = LruCache::new();
+
+        let misses = |lru_cache: &mut LruCache<4, 1, 16>, offset| {
+            Simulation::<1_600>::simulate_relocated(lru_cache, trace, offset, false).unwrap()[0]
+                .miss_count
+        };
+        assert_eq!(misses(&mut lru_cache, 0), 3);
+        assert_eq!(misses(&mut lru_cache, 8), 5 + 3 * 2);
+
+        assert!(
+            Simulation::<1_600>::simulate_relocated(&mut lru_cache, trace, usize::MAX, false)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn cache_hit_predicates() {
+        assert!(CacheHit::Hit.is_hit());
+        assert!(!CacheHit::Hit.is_miss());
+        assert_eq!(CacheHit::Hit.evicted(), None);
+
+        let cold_miss = CacheHit::Miss { prev: None };
+        assert!(!cold_miss.is_hit());
+        assert!(cold_miss.is_miss());
+        assert_eq!(cold_miss.evicted(), None);
+
+        let eviction = CacheHit::Miss { prev: Some(0x40) };
+        assert!(eviction.is_miss());
+        assert_eq!(eviction.evicted(), Some(0x40));
+    }
+
+    #[test]
+    fn expected_instruction_count() {
+        let trace = |expected: usize| {
+            format!(
+                "// @expect_instructions {expected}\ncompare 'a' {{\n    loop(3) {{\n        0x0..32..0x10\n    }}\n}}\n\ncompare 'b' {{\n    0x0..32..0x8\n}}\n"
+            )
+        };
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+
+        assert!(Simulation::<1_600>::simulate(&mut lru_cache, &trace(3 * 4 + 2), false).is_ok());
+        assert_eq!(
+            Simulation::<1_600>::simulate(&mut lru_cache, &trace(12), false)
+                .unwrap_err()
+                .to_string(),
+            "expected 12 instructions but the traces have 14"
+        );
+        assert!(
+            Simulation::<1_600>::simulate(&mut lru_cache, "// @expect_instructions many\n", false)
+                .is_err()
+        );
+
+        let mismatch = |result: Result<_, SimulationError>| {
+            matches!(
+                result,
+                Err(SimulationError::InstructionCountMismatch {
+                    expected: 12,
+                    actual: 14
+                })
+            )
+        };
+        assert!(mismatch(Simulation::<1_600>::simulate_relocated(
+            &mut lru_cache,
+            &trace(12),
+            0x1000,
+            false
+        )));
+        // the skipped instructions still count
+        assert!(mismatch(Simulation::<1_600>::simulate_windowed(
+            &mut lru_cache,
+            &trace(12),
+            0x0,
+            0x4,
+            false
+        )));
+        assert!(mismatch(
+            Simulation::<1_600>::simulate_named(&mut lru_cache, &trace(12), "b", false)
+                .map(|result| vec![result])
+        ));
+        assert!(mismatch(Simulation::<1_600>::simulate_opt::<16, 2, 64>(
+            &trace(12)
+        )));
+        assert!(
+            Simulation::<1_600>::simulate_named(&mut lru_cache, &trace(14), "b", false).is_ok()
+        );
+        // a limited run doesn't simulate all instructions
+        assert!(
+            Simulation::<1_600>::simulate_limited(&mut lru_cache, &trace(12), 4, false).is_ok()
+        );
+    }
+
+    #[test]
+    fn retaining_keeps_lines_across_blocks() {
+        let trace = "compare 'a' {\n    0x0..32..0x40\n}\n\ncompare 'b' {\n    0x0..32..0x40\n    0x80..32..0x84\n}\n";
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+
+        let results =
+            Simulation::<1_600>::simulate_retaining(&mut lru_cache, trace, false).unwrap();
+        assert_eq!((results[0].hit_count, results[0].miss_count), (15, 1));
+        // 'b' starts with the line of 'a' resident
+        assert_eq!((results[1].hit_count, results[1].miss_count), (16, 1));
+        assert!(lru_cache.contains(0x0));
+        assert!(lru_cache.contains(0x80));
+
+        let results = Simulation::<1_600>::simulate(&mut lru_cache, trace, false).unwrap();
+        assert_eq!(results[1].miss_count, 2);
+    }
+
+    #[test]
+    fn small_quantum_interferes_more() {
+        // each thread alone fits into the two ways of the single set, together they do not
+        let trace = "compare 'a' {\n    loop(4) {\n        0x0..32..0x80\n    }\n}\n\ncompare 'b' {\n    loop(4) {\n        0x1000..32..0x1080\n    }\n}\n";
+        let mut lru_cache: LruCache<1, 2, 64> = LruCache::new();
+        let misses = |lru_cache: &mut LruCache<1, 2, 64>, quantum: usize| {
+            Simulation::<1_600>::simulate_interleaved(lru_cache, trace, "a", "b", quantum)
+                .unwrap()
+                .iter()
+                .map(|result| (result.name.clone(), result.miss_count))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            misses(&mut lru_cache, 1_000),
+            [
+                (String::from("a"), 2),
+                (String::from("b"), 2),
+                (String::from("a+b"), 4)
+            ]
+        );
+        assert!(misses(&mut lru_cache, 8)[2].1 > 4);
+        assert!(matches!(
+            Simulation::<1_600>::simulate_interleaved(&mut lru_cache, trace, "a", "c", 8),
+            Err(SimulationError::UnknownTrace(_))
+        ));
+        assert!(matches!(
+            Simulation::<1_600>::simulate_interleaved(&mut lru_cache, trace, "a", "b", 0),
+            Err(SimulationError::ZeroQuantum)
+        ));
+
+        // the lines are touched by many slices but counted once
+        let results =
+            Simulation::<1_600>::simulate_interleaved(&mut lru_cache, trace, "a", "b", 8).unwrap();
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| (result.distinct_lines, result.distinct_addresses))
+                .collect::<Vec<_>>(),
+            [(2, 0x80), (2, 0x80), (4, 0x100)]
+        );
+
+        let warm = format!("preamble 'warm'\n'warm' {{\n    0x0..32..0x80\n}}\n\n{trace}");
+        let results =
+            Simulation::<1_600>::simulate_interleaved(&mut lru_cache, &warm, "a", "b", 1_000)
+                .unwrap();
+        assert_eq!(results[0].miss_count, 0);
+    }
+
+    #[test]
+    fn measured_miss_rate_error() {
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+        // 2 compulsory misses of 32 instructions
+        let results = Simulation::<1_600>::simulate(
+            &mut lru_cache,
+            "// @measured_miss_rate 0.1\ncompare 'a' {\n    0x0..32..0x80\n}\n",
+            false,
+        )
+        .unwrap();
+        let params = Params::builder()
+            .cycles_hit(1)
+            .cycles_miss(25)
+            .build()
+            .unwrap();
+        let report = results[0].report(&params);
+
+        assert_eq!(report.measured_miss_rate, Some(0.1));
+        assert!((report.miss_rate_error.unwrap() - 0.0375).abs() < 1e-9);
+        assert!(
+            report
+                .to_string()
+                .contains("Measured Misses: 10.000% (simulation off by 3.750 percentage points)")
+        );
+        assert!(
+            Simulation::<1_600>::simulate(
+                &mut lru_cache,
+                "// @measured_miss_rate 3.7\ncompare 'a' {\n    0x0..32..0x80\n}\n",
+                false
+            )
+            .is_err()
+        );
+
+        // a directive belongs to the block it is in or directly follows
+        let trace = "compare 'a' {\n    0x0..32..0x80\n}\n\n// @measured_miss_rate 0.2\ncompare 'b' {\n    0x0..32..0x80\n}\n\ncompare 'c' {\n    // @measured_miss_rate 0.3\n    0x0..32..0x80\n}\n";
+        let measured = |results: Vec<Simulation<1_600>>| {
+            results
+                .iter()
+                .map(|result| result.report(&params).measured_miss_rate)
+                .collect::<Vec<_>>()
+        };
+        let expected = [None, Some(0.2), Some(0.3)];
+        assert_eq!(
+            measured(Simulation::simulate(&mut lru_cache, trace, false).unwrap()),
+            expected
+        );
+        assert_eq!(
+            measured(Simulation::simulate_limited(&mut lru_cache, trace, 8, false).unwrap()),
+            expected
+        );
+        assert_eq!(
+            measured(Simulation::simulate_interleaved(&mut lru_cache, trace, "b", "c", 4).unwrap()),
+            [Some(0.2), Some(0.3), None]
+        );
+    }
+
+    #[test]
+    fn miss_burst_increases_miss_latency() {
+        let with_misses = |misses: [usize; 4]| Simulation::<1_000> {
+            hit_count: 12,
+            miss_count: 4,
+            hits: (0..16).map(|i| !misses.contains(&i)).collect(),
+            ..Default::default()
+        };
+        let params = Params::builder()
+            .cycles_hit(1)
+            .cycles_miss(25)
+            .contention(4, 100)
+            .build()
+            .unwrap();
+
+        // the miss rate of the window is 1 for every miss of the burst
+        let burst = with_misses([0, 1, 2, 3]).report(&params);
+        assert!((burst.miss_time_us - (4.0 * 25.0 + 4.0 * 100.0) / 1_000.0).abs() < 1e-9);
+
+        // only the first sparse miss has no hits in its window
+        let sparse = with_misses([0, 4, 8, 12]).report(&params);
+        assert!((sparse.miss_time_us - (4.0 * 25.0 + 100.0 + 3.0 * 25.0) / 1_000.0).abs() < 1e-9);
+        assert!(burst.miss_time_us > sparse.miss_time_us);
+
+        // the latency does not depend on logging the memory accesses
+        let trace = "compare 'a' {\n    loop(2) {\n        0x0..32..0x100\n    }\n}\n";
+        let mut lru_cache: LruCache<4, 1, 16> = LruCache::new();
+        let mut miss_time_us = |log_memory_accesses| {
+            Simulation::<1_000>::simulate(&mut lru_cache, trace, log_memory_accesses).unwrap()[0]
+                .report(&params)
+                .miss_time_us
+        };
+        let logged = miss_time_us(true);
+        assert_eq!(miss_time_us(false), logged);
+        assert!(logged > 32.0 * 25.0 / 1_000.0);
+    }
+
+    #[test]
+    fn line_crossing_penalty() {
+        // the instruction at 0x3E spans the cache-lines 0x0 and 0x40
+        let trace = "compare 'a' {\n    loop(2) {\n        0x3E..32..0x42\n    }\n}\n";
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+        let results = Simulation::<1_000>::simulate(&mut lru_cache, trace, false).unwrap();
+        assert_eq!(results[0].line_crossings, 2);
+
+        let params = |line_cross_penalty| {
+            Params::builder()
+                .cycles_hit(1)
+                .cycles_miss(25)
+                .line_cross_penalty(line_cross_penalty)
+                .build()
+                .unwrap()
+        };
+        let without = results[0].report(&params(0));
+        let with = results[0].report(&params(3));
+        assert!((with.total_time_us - without.total_time_us - 2.0 * 3.0 / 1_000.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn chrome_trace_events() {
+        let trace = "compare 'a' {\n    0x0..32..0x48\n}\n";
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+        let params = Params::builder()
+            .cycles_hit(1)
+            .cycles_miss(25)
+            .build()
+            .unwrap();
+
+        let results = Simulation::<1_000>::simulate(&mut lru_cache, trace, false).unwrap();
+        assert_eq!(results[0].to_chrome_trace(), None);
+        let results = Simulation::<1_000>::simulate(&mut lru_cache, trace, true).unwrap();
+        assert_eq!(results[0].to_chrome_trace(), None);
+
+        let results = Simulation::<1_000>::simulate_timed(&mut lru_cache, trace, &params).unwrap();
+        let chrome_trace = results[0].to_chrome_trace().unwrap();
+        assert!(chrome_trace.starts_with("{\"traceEvents\":[{\"name\":\"0x0\",\"cat\":\"miss\",\"ph\":\"X\",\"ts\":0,\"dur\":0.025,"));
+        assert_eq!(chrome_trace.matches("\"ph\":\"X\"").count(), 18);
+        assert_eq!(chrome_trace.matches("\"cat\":\"miss\"").count(), 2);
+        assert_eq!(chrome_trace.matches("\"cat\":\"hit\"").count(), 16);
+
+        // a zero cycle access still makes a timed log
+        let free = Params {
+            cycles_hit: 0,
+            cycles_miss: 0,
+            ..params
+        };
+        let results = Simulation::<1_000>::simulate_timed(&mut lru_cache, trace, &free).unwrap();
+        let chrome_trace = results[0].to_chrome_trace().unwrap();
+        assert_eq!(chrome_trace.matches("\"dur\":0,").count(), 18);
+    }
+
+    #[test]
+    fn data_accesses_evict_instructions_from_shared_l2() {
+        let trace = |data: &str| {
+            format!(
+                "compare 'a' {{\n    0x0..32..0x4\n    ifetch 0x100..32..0x104\n{data}    0x0..32..0x4\n}}\n"
+            )
+        };
+        let simulate = |trace: &str| {
+            let mut l1i: LruCache<1, 1, 16> = LruCache::new();
+            let mut l1d: LruCache<1, 1, 16> = LruCache::new();
+            let mut l2: LruCache<1, 2, 16> = LruCache::new();
+            Simulation::<1_600>::simulate_hierarchy(&mut l1i, &mut l1d, &mut l2, trace)
+                .unwrap()
+                .remove(0)
+        };
+
+        // the L1-I only holds a single line, the last fetch of 0x0 hits in the L2
+        let instructions_only = simulate(&trace(""));
+        assert_eq!(instructions_only.l1i, LevelCounts { hits: 0, misses: 3 });
+        assert_eq!(
+            instructions_only.l2_instruction,
+            LevelCounts { hits: 1, misses: 2 }
+        );
+
+        // the two data lines replace both instruction lines in the L2
+        let interleaved = simulate(&trace("    load 0x200\n    store 0x300\n"));
+        assert_eq!(interleaved.l1d, LevelCounts { hits: 0, misses: 2 });
+        assert_eq!(interleaved.l2_data, LevelCounts { hits: 0, misses: 2 });
+        assert_eq!(
+            interleaved.l2_instruction,
+            LevelCounts { hits: 0, misses: 3 }
+        );
+    }
+
+    #[test]
+    fn diff_flags_regressions() {
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+        let baseline = Simulation::<1_000>::simulate(
+            &mut lru_cache,
+            "compare 'a' {\n    0x0..32..0x100\n}\n\ncompare 'b' {\n    0x0..32..0x100\n}\n\ncompare 'removed' {\n    0x0..32..0x4\n}\n",
+            false,
+        )
+        .unwrap();
+        // 'a' gets about 40% slower, 'b' gets a single additional hit
+        let candidate = Simulation::<1_000>::simulate(
+            &mut lru_cache,
+            "compare 'a' {\n    0x0..32..0x100\n    0x200..32..0x250\n}\n\ncompare 'b' {\n    0x0..32..0x100\n    0x0..32..0x4\n}\n\ncompare 'added' {\n    0x0..32..0x4\n}\n",
+            false,
+        )
+        .unwrap();
+        let params = Params::builder()
+            .cycles_hit(1)
+            .cycles_miss(25)
+            .build()
+            .unwrap();
+
+        let regressions = Simulation::diff(&baseline, &candidate, &params, 5.0);
+        let names: Vec<&str> = regressions.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "added", "removed"]);
+        assert_eq!(
+            regressions[1],
+            Regression {
+                name: String::from("added"),
+                baseline_time_us: None,
+                candidate_time_us: Some(0.025),
+            }
+        );
+        assert_eq!(regressions[2].candidate_time_us, None);
+        assert_eq!(Simulation::diff(&baseline, &baseline, &params, 0.0), vec![]);
+    }
+
+    #[test]
+    fn clients_confined_to_their_ways() {
+        let trace = "compare 'a' {\n    loop(4) {\n        client 0\n        0x0..32..0x4\n        0x10..32..0x14\n        client 1\n        0x100..32..0x104\n        0x110..32..0x114\n        0x120..32..0x124\n    }\n}\n";
+
+        // five lines accessed cyclically miss every time in 4 LRU ways
+        let mut shared: LruCache<1, 4, 4> = LruCache::new();
+        let results = Simulation::<1_600>::simulate(&mut shared, trace, false).unwrap();
+        assert_eq!(results[0].miss_count, 20);
+
+        let mut partitioned: LruCache<1, 4, 4> = LruCache::new()
+            .with_way_mask(0, 0b0001)
+            .with_way_mask(1, 0b1110);
+        let results = Simulation::<1_600>::simulate(&mut partitioned, trace, false).unwrap();
+        assert_eq!(results[0].miss_count, 8 + 3);
+    }
+
+    #[test]
+    fn reports_sorted_by_name() {
+        let trace = "compare 'b' {\n    0x0..32..0x200\n}\n\ncompare 'c' {\n    0x0..32..0x100\n}\n\ncompare 'a' {\n    loop(2) {\n        0x0..32..0x100\n    }\n}\n";
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+        let results = Simulation::<1_600>::simulate(&mut lru_cache, trace, false).unwrap();
+        let names = |sort_order| {
+            let params = Params::builder()
+                .cycles_hit(1)
+                .cycles_miss(25)
+                .sort_order(sort_order)
+                .build()
+                .unwrap();
+            Simulation::reports(&results, params)
+                .into_iter()
+                .map(|report| (report.name, report.relative_time_pct == Some(0.0)))
+                .collect::<Vec<_>>()
+        };
+
+        // 'c' stays the baseline
+        let order = |names: [&str; 3]| names.map(|name| (String::from(name), name == "c")).to_vec();
+        assert_eq!(names(SortOrder::ByTime), order(["c", "a", "b"]));
+        assert_eq!(names(SortOrder::ByName), order(["a", "b", "c"]));
+        assert_eq!(names(SortOrder::Source), order(["b", "c", "a"]));
+    }
+
+    #[test]
+    fn preamble_warms_the_cache() {
+        let trace = "'warm' {\n    0x0..32..0x40\n}\n\ncompare 'a' {\n    0x0..32..0x80\n}\n";
+        let mut lru_cache: LruCache<16, 2, 16> = LruCache::new();
+        let cold = Simulation::<1_600>::simulate(&mut lru_cache, trace, false).unwrap();
+        let warm = Simulation::<1_600>::simulate(
+            &mut lru_cache,
+            &format!("preamble 'warm'\n{trace}"),
+            false,
+        )
+        .unwrap();
+
+        assert_eq!((cold[0].hit_count, cold[0].miss_count), (24, 8));
+        assert_eq!((warm[0].hit_count, warm[0].miss_count), (28, 4));
+
+        let parallel = Simulation::<1_600>::simulate_parallel(
+            &lru_cache,
+            &format!("preamble 'warm'\n{trace}"),
+            false,
+        )
+        .unwrap();
+        assert_eq!(parallel[0].miss_count, 4);
+
+        let warm_trace = format!("preamble 'warm'\n{trace}");
+        let limited =
+            Simulation::<1_600>::simulate_limited(&mut lru_cache, &warm_trace, 100, false).unwrap();
+        assert_eq!(limited[0].miss_count, 4);
+        let windowed =
+            Simulation::<1_600>::simulate_windowed(&mut lru_cache, &warm_trace, 0x40, 0x80, false)
+                .unwrap();
+        assert_eq!((windowed[0].hit_count, windowed[0].miss_count), (12, 4));
+        let relocated =
+            Simulation::<1_600>::simulate_relocated(&mut lru_cache, &warm_trace, 0x100, false)
+                .unwrap();
+        assert_eq!(relocated[0].miss_count, 4);
+        let named =
+            Simulation::<1_600>::simulate_named(&mut lru_cache, &warm_trace, "a", false).unwrap();
+        assert_eq!(named.miss_count, 4);
+        let split =
+            Simulation::<1_600>::simulate_split_report(&mut lru_cache, &warm_trace).unwrap();
+        assert_eq!(split[0].1.full_miss, 4);
+
+        let mut l1d: LruCache<16, 2, 16> = LruCache::new();
+        let mut l2: LruCache<16, 2, 16> = LruCache::new();
+        let hierarchy =
+            Simulation::<1_600>::simulate_hierarchy(&mut lru_cache, &mut l1d, &mut l2, &warm_trace)
+                .unwrap();
+        assert_eq!(hierarchy[0].l1i.misses, 4);
+        assert_eq!(hierarchy[0].l2_instruction.misses, 4);
+
+        assert!(
+            Simulation::<1_600>::simulate(&mut lru_cache, &format!("preamble 'x'\n{trace}"), false)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn simulation_error_variants() {
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+
+        let error =
+            Simulation::<1_600>::simulate_file(&mut lru_cache, "traces/missing.trace", false)
+                .unwrap_err();
+        assert!(matches!(
+            &error,
+            SimulationError::Io { source, .. } if source.kind() == std::io::ErrorKind::NotFound
+        ));
+        assert!(error.to_string().starts_with("failed to read file: "));
+
+        assert!(matches!(
+            Simulation::<1_600>::simulate(&mut lru_cache, "", false),
+            Err(SimulationError::Empty)
+        ));
+        assert!(matches!(
+            Simulation::<1_600>::simulate(&mut lru_cache, "'a' {\n    0x0..32..0x4\n}\n", false),
+            Err(SimulationError::NoComparableTraces)
+        ));
+        assert!(matches!(
+            Simulation::<1_600>::simulate(&mut lru_cache, "compare 'a' {\n    0x0..\n}\n", false),
+            Err(SimulationError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn monte_carlo_confidence_interval() {
+        let mut lru_cache: LruCache<4, 1, 16> = LruCache::new();
+
+        let results = Simulation::<1_600>::simulate_monte_carlo(
+            &mut lru_cache,
+            "compare 'a' {\n    loop(2) {\n        0x0..32..0x40\n    }\n}\n",
+            20,
+            7,
+        )
+        .unwrap();
+        assert_eq!(results[0].runs, 20);
+        assert_eq!(results[0].mean_miss_rate, 4.0 / 32.0);
+        assert_eq!(results[0].confidence_interval, (0.125, 0.125));
+
+        let results = Simulation::<1_600>::simulate_monte_carlo(
+            &mut lru_cache,
+            "compare 'b' {\n    random(8) in 0x0..32..0x400\n}\n",
+            20,
+            7,
+        )
+        .unwrap();
+        let (low, high) = results[0].confidence_interval;
+        assert!(low < results[0].mean_miss_rate && results[0].mean_miss_rate < high);
+
+        assert!(matches!(
+            Simulation::<1_600>::simulate_monte_carlo(
+                &mut lru_cache,
+                "compare 'a' {\n    0x0..32..0x4\n}\n",
+                0,
+                7
+            ),
+            Err(SimulationError::ZeroRuns)
+        ));
+    }
+
+    #[test]
+    fn hook_sees_every_outcome() {
+        let mut lru_cache: LruCache<2, 1, 8> = LruCache::new();
+        let mut outcomes = Vec::new();
+        let results = Simulation::<1_600>::simulate_with_hook(
+            &mut lru_cache,
+            "compare 'a' {\n    0x0..32..0x10\n    0x8..32..0xC\n}\n",
+            |instruction, cache_hit| outcomes.push((instruction.address, cache_hit.is_hit())),
+        )
+        .unwrap();
+
+        assert_eq!(
+            outcomes,
+            [
+                (0x0, false),
+                (0x4, true),
+                (0x8, false),
+                (0xC, true),
+                (0x8, true)
+            ]
+        );
+        assert_eq!((results[0].hit_count, results[0].miss_count), (3, 2));
+    }
+
+    #[test]
+    fn simulate_from_reader() {
+        let trace = "compare 'a' {\n    0x0..32..0x80\n}\n";
+        let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+        let from_reader = Simulation::<1_600>::simulate_reader(
+            &mut lru_cache,
+            std::io::Cursor::new(trace.as_bytes()),
+            false,
+        )
+        .unwrap();
+        let from_str = Simulation::<1_600>::simulate(&mut lru_cache, trace, false).unwrap();
+
+        assert_eq!(from_reader[0].name, "a");
+        assert_eq!(
+            (from_reader[0].hit_count, from_reader[0].miss_count),
+            (from_str[0].hit_count, from_str[0].miss_count)
+        );
+        assert!(matches!(
+            Simulation::<1_600>::simulate_reader(&mut lru_cache, &[0xFF, 0xFE][..], false),
+            Err(SimulationError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn scan_once_pollutes_every_fill() {
+        // every 4 byte line holds a single 32 bit instruction that is never fetched again
+        let mut lru_cache: LruCache<4, 1, 4> = LruCache::new();
+        let results = Simulation::<1_600>::simulate(
+            &mut lru_cache,
+            "compare 'a' {\n    0x0..32..0x100\n}\n",
+            false,
+        )
+        .unwrap();
+
+        // the last 4 lines are still resident
+        assert_eq!(results[0].miss_count, 64);
+        assert_eq!(results[0].polluting_fills, 64 - 4);
+        let params = Params::builder()
+            .cycles_hit(1)
+            .cycles_miss(25)
+            .build()
+            .unwrap();
+        assert_eq!(results[0].report(&params).polluting_fills, 60);
+
+        let results = Simulation::<1_600>::simulate(
+            &mut lru_cache,
+            "compare 'a' {\n    loop(2) {\n        0x0..32..0x100\n    }\n}\n",
+            false,
+        )
+        .unwrap();
+        assert_eq!(results[0].polluting_fills, 2 * 64 - 4);
+
+        let mut lru_cache: LruCache<64, 1, 4> = LruCache::new();
+        let results = Simulation::<1_600>::simulate(
+            &mut lru_cache,
+            "compare 'a' {\n    loop(2) {\n        0x0..32..0x100\n    }\n}\n",
+            false,
+        )
+        .unwrap();
+        assert_eq!(results[0].polluting_fills, 0);
+    }
+
+    #[test]
+    fn addresses_in_decimal() {
+        let mut lru_cache: LruCache<1, 1, 16> = LruCache::new();
+        let results = Simulation::<1_600>::simulate(
+            &mut lru_cache,
+            "compare 'a' {\n    0x1000..32..0x1004\n    0x2000..32..0x2004\n}\n",
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(
+            results[0].format_explained_with(Radix::Dec),
+            "4096 Miss(compulsory)\n8192 Miss(compulsory) evicted=4096"
+        );
+        assert_eq!(
+            results[0].format_explained(),
+            "0x1000 Miss(compulsory)\n0x2000 Miss(compulsory) evicted=0x1000"
+        );
+        let cache_hit = CacheHit::Miss { prev: Some(0x1000) };
+        assert_eq!(cache_hit.format_with(Radix::Dec), "Miss prev=4096");
+        assert_eq!(
+            cache_hit.format_with(Radix::Bin),
+            "Miss prev=0b1000000000000"
+        );
+        assert_eq!(cache_hit.to_string(), "Miss prev=0x1000");
+    }
+
+    #[test]
+    fn distinct_addresses_and_lines() {
+        // 5 lines of 16 bytes, each touched twice by a single 32 bit instruction
+        let trace = "compare 'a' {\n    loop(2) {\n        0x0..32..0x4\n        0x10..32..0x14\n        0x20..32..0x24\n        0x30..32..0x34\n        0x40..32..0x44\n    }\n}\n";
+        let mut lru_cache: LruCache<2, 1, 16> = LruCache::new();
+        let results = Simulation::<1_600>::simulate(&mut lru_cache, trace, false).unwrap();
+
+        assert_eq!(results[0].distinct_addresses, 5 * 4);
+        // more lines than the 2 of the cache
+        assert_eq!(results[0].distinct_lines, 5);
+        assert_eq!(results[0].miss_count, 10);
+    }
+
+    #[test]
+    fn compare_rejects_mixed_geometries() {
+        let trace = "compare 'a' {\n    0x0..32..0x100\n}\n";
+        let params = Params::builder()
+            .cycles_hit(1)
+            .cycles_miss(25)
+            .build()
+            .unwrap();
+
+        let mut results =
+            Simulation::<1_600>::simulate(&mut LruCache::<16, 2, 64>::new(), trace, false).unwrap();
+        assert!(Simulation::compare(&results, params).is_ok());
+        assert_eq!(
+            results[0].geometry(),
+            Some(Geometry {
+                sets: 16,
+                ways: 2,
+                line_size: 64
+            })
+        );
+
+        results.extend(
+            Simulation::<1_600>::simulate(&mut LruCache::<16, 4, 64>::new(), trace, false).unwrap(),
+        );
+        assert!(matches!(
+            Simulation::compare(&results, params),
+            Err(SimulationError::GeometryMismatch { .. })
+        ));
+        let error = Simulation::write_compare(&results, params, &mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(
+            error.to_string(),
+            "trace 'a' was simulated with 16 sets, 4 ways, 64B lines but trace 'a' with 16 sets, 2 ways, 64B lines"
+        );
+    }
+
+    #[test]
+    fn sequential_trace_prefers_long_lines() {
+        let params = Params::builder()
+            .cycles_hit(1)
+            .cycles_miss(25)
+            .build()
+            .unwrap();
+        let trace = "compare 'a' {\n    0x0..32..0x4000\n}\n";
+
+        assert_eq!(
+            Simulation::<1_600>::best_line_size_4_way(trace, 4 * 1024, &params).unwrap(),
+            128
+        );
+        assert!(matches!(
+            Simulation::<1_600>::best_line_size_4_way(trace, 3 * 1024, &params),
+            Err(SimulationError::UnsupportedCacheSize(3072))
+        ));
+    }
+
+    #[test]
+    fn timestamps_add_up_to_total_cycles() {
+        let params = Params::builder()
+            .cycles_hit(1)
+            .cycles_miss(25)
+            .build()
+            .unwrap();
+        let mut lru_cache: LruCache<4, 1, 16> = LruCache::new();
+        let results = Simulation::<1_600>::simulate_timed(
+            &mut lru_cache,
+            "compare 'a' {\n    loop(2) {\n        0x0..32..0x80\n    }\n}\n",
+            &params,
+        )
+        .unwrap();
+
+        let timestamps: Vec<u64> = results[0]
+            .memory_accesses
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|access| access.timestamp.unwrap())
+            .collect();
+        assert_eq!(timestamps.len(), 64);
+        assert!(timestamps.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(timestamps[..2], [25, 26]);
+
+        let total_cycles = u64::from(results[0].hit_count) * u64::from(params.cycles_hit)
+            + u64::from(results[0].miss_count) * u64::from(params.cycles_miss);
+        assert_eq!(*timestamps.last().unwrap(), total_cycles);
+    }
+
+    #[test]
+    fn timestamps_follow_the_params() {
+        // the instruction at 0x3E spans the cache-lines 0x0 and 0x40
+        let trace = "compare 'a' {\n    loop(2) {\n        0x3E..32..0x42\n    }\n}\n";
+        let timestamps = |params: Params| {
+            let mut lru_cache: LruCache<16, 2, 64> = LruCache::new();
+            Simulation::<1_000>::simulate_timed(&mut lru_cache, trace, &params).unwrap()[0]
+                .memory_accesses
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|access| access.timestamp.unwrap())
+                .collect::<Vec<_>>()
+        };
+        let params = || {
+            Params::builder()
+                .cycles_hit(1)
+                .cycles_miss(25)
+                .line_cross_penalty(3)
+        };
+
+        assert_eq!(timestamps(params().build().unwrap()), [28, 32]);
+        assert_eq!(
+            timestamps(
+                params()
+                    .cache_model(CacheModel::HitUnderMiss)
+                    .build()
+                    .unwrap()
+            ),
+            [28, 31]
+        );
+        assert_eq!(
+            timestamps(params().contention(4, 100).build().unwrap()),
+            [128, 132]
+        );
     }
 }
@@ -1,17 +1,35 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
 
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
-use winnow::ascii::{line_ending, multispace0, space0, space1, till_line_ending};
+use winnow::ascii::{float, line_ending, multispace0, space0, space1, till_line_ending};
 use winnow::combinator::{
     alt, cut_err, delimited, eof, fail, opt, peek, preceded, repeat, repeat_till, separated_pair,
     terminated,
 };
-use winnow::error::{ContextError, ParseError, StrContext};
-use winnow::stream::AsChar;
+use winnow::error::{ContextError, ErrMode, ParseError, StrContext, StrContextValue};
+use winnow::stream::{AsChar, Stateful};
 use winnow::token::take_while;
 use winnow::{ModalResult, Parser};
 
+use crate::parse;
+
+/// the state of the nesting check in [`block`]
+#[derive(Debug, Clone, Copy)]
+struct Nesting {
+    /// blocks currently being parsed, including the body of the named block
+    open: usize,
+    limit: usize,
+}
+
+type Input<'a> = Stateful<&'a str, Nesting>;
+
+/// the context of the error [`block`] fails with if [`Nesting::limit`] is exceeded
+const NESTED_TOO_DEEP: StrContext = StrContext::Label("nesting depth");
+/// the context of the error [`looop`] fails with at a count that doesn't fit into a `usize`
+const LOOP_COUNT_TOO_LARGE: StrContext = StrContext::Label("loop count too large");
+
 #[derive(Debug)]
 pub enum TraceParseError<'a> {
     ParseError(ParseError<&'a str, ContextError>),
@@ -29,20 +47,124 @@ impl std::fmt::Display for TraceParseError<'_> {
 
 impl std::error::Error for TraceParseError<'_> {}
 
+impl TraceParseError<'_> {
+    /// detaches the error from the parsed input, parse errors keep only their message
+    pub fn into_owned(self) -> TraceParseError<'static> {
+        match self {
+            TraceParseError::ParseError(parse_error) => {
+                TraceParseError::SyntaxError(parse_error.to_string())
+            }
+            TraceParseError::SyntaxError(e) => TraceParseError::SyntaxError(e),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct TraceFile<'a> {
     named_blocks: HashMap<&'a str, NamedBlock<'a>>,
+    /// block names in the order they are defined in, iteration follows this order
+    definition_order: Vec<&'a str>,
+    metadata: HashMap<String, String>,
+    /// set by a `// @expect_instructions` comment at the start of the file
+    expected_instructions: Option<usize>,
+    /// per block the miss rate set by a `// @measured_miss_rate` comment
+    measured_miss_rates: HashMap<&'a str, f64>,
+    /// block set by `preamble 'name'` that warms the cache before every compare block
+    preamble: Option<&'a str>,
+    /// ops following a `tailcall` that were dropped while parsing
+    unreachable_warnings: Vec<String>,
 }
 
 impl<'a> TryFrom<&'a str> for TraceFile<'a> {
     type Error = TraceParseError<'a>;
 
     fn try_from(input: &'a str) -> Result<Self, Self::Error> {
-        let blocks: Vec<NamedBlock<'a>> = repeat(0.., preceded(multispace, named_block))
-            .context(StrContext::Label("trace blocks"))
+        Self::try_from_with_max_depth(input, Self::DEFAULT_MAX_DEPTH)
+    }
+}
+
+impl<'a> TraceFile<'a> {
+    /// the nesting limit of [`TraceFile::try_from`], deeper files are rejected while parsing
+    /// instead of overflowing the stack
+    pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+    /// like [`TraceFile::try_from`] but files with loops, tiles, interrupts or switches nested
+    /// more than `max_depth` levels deep are rejected
+    pub fn try_from_with_max_depth(
+        input: &'a str,
+        max_depth: usize,
+    ) -> Result<Self, TraceParseError<'a>> {
+        let top_level = terminated(
+            repeat(
+                0..,
+                preceded(
+                    multispace,
+                    alt((
+                        preamble.map(TopLevel::Preamble),
+                        named_block.map(TopLevel::Block),
+                    )),
+                )
+                .with_taken(),
+            )
+            .context(StrContext::Label("trace blocks")),
+            (multispace, eof)
+                .context(StrContext::Label("input after last block"))
+                .context(StrContext::Expected(StrContextValue::Description(
+                    "block definition",
+                ))),
+        );
+        let items: Vec<(TopLevel<'a>, &'a str)> = with_nesting_limit(max_depth, top_level)
             .parse(input)
-            .map_err(TraceParseError::ParseError)?;
+            .map_err(|err| {
+                let has_context = |marker| err.inner().context().any(|context| *context == marker);
+                if has_context(NESTED_TOO_DEEP) {
+                    TraceParseError::SyntaxError(format!(
+                        "loops, tiles, interrupts or switches nested more than the limit of {max_depth} levels deep"
+                    ))
+                } else if has_context(LOOP_COUNT_TOO_LARGE) {
+                    let count = &input[err.offset()..];
+                    let count = &count[..count.find(|c: char| !c.is_ascii_digit()).unwrap_or(count.len())];
+                    TraceParseError::SyntaxError(format!("loop count {count} is too large"))
+                } else {
+                    TraceParseError::ParseError(err)
+                }
+            })?;
+
+        let mut blocks = Vec::new();
+        let mut preamble = None;
+        let mut measured_miss_rates = HashMap::new();
+        // the comments after an item belong to the next one
+        let mut comments_before = "";
+        for (item, text) in items {
+            let (text, comments_after) = split_trailing_comments(text);
+            match item {
+                TopLevel::Block(block) => {
+                    if let Some(measured_miss_rate) = parse_measured_miss_rate(text)?
+                        .or(parse_measured_miss_rate(comments_before)?)
+                    {
+                        measured_miss_rates.insert(block.name, measured_miss_rate);
+                    }
+                    blocks.push(block);
+                }
+                TopLevel::Preamble(name) if preamble.is_some() => {
+                    return Err(TraceParseError::SyntaxError(format!(
+                        "preamble '{name}' set after preamble '{}'",
+                        preamble.unwrap()
+                    )));
+                }
+                TopLevel::Preamble(name) => preamble = Some(name),
+            }
+            comments_before = comments_after;
+        }
+
+        let mut unreachable_warnings = Vec::new();
+        for block in &mut blocks {
+            drop_unreachable(&mut block.ops, block.name, &mut unreachable_warnings);
+        }
 
+        let metadata = parse_metadata(input)?;
+        let expected_instructions = parse_expected_instructions(input)?;
+        let definition_order: Vec<&'a str> = blocks.iter().map(|block| block.name).collect();
         let mut block_map = HashMap::new();
         for block in blocks {
             if block_map.contains_key(block.name) {
@@ -55,6 +177,10 @@ impl<'a> TryFrom<&'a str> for TraceFile<'a> {
             block_map.insert(block.name, block);
         }
 
+        for name in &definition_order {
+            resolve_ranges(&mut block_map.get_mut(name).unwrap().ops, None, &mut 0)?;
+        }
+
         // the order we go through all statements does not matter
         // we just want to check if all functions mentioned have a corresponding definition
         let mut queue =
@@ -63,14 +189,36 @@ impl<'a> TryFrom<&'a str> for TraceFile<'a> {
             match stmt {
                 Op::BlockCall {
                     block_name: function_name,
-                } => {
-                    if !block_map.contains_key(function_name) {
-                        return Err(TraceParseError::SyntaxError(format!(
-                            "unknown function '{function_name}()'"
-                        )));
-                    }
                 }
-                Op::Loop { block, .. } => {
+                | Op::TailCall {
+                    block_name: function_name,
+                } if !block_map.contains_key(function_name) => {
+                    return Err(TraceParseError::SyntaxError(format!(
+                        "unknown function '{function_name}()'"
+                    )));
+                }
+                Op::Interrupt { every: 0, .. } => {
+                    return Err(TraceParseError::SyntaxError(String::from(
+                        "interrupt interval must be nonzero",
+                    )));
+                }
+                Op::Zipf {
+                    line_size,
+                    addr_start,
+                    instr_length,
+                    addr_end,
+                    ..
+                } if *line_size == 0
+                    || !(line_size * 8).is_multiple_of(*instr_length)
+                    || !addr_start.is_multiple_of(*line_size)
+                    || !addr_end.is_multiple_of(*line_size) =>
+                {
+                    return Err(TraceParseError::SyntaxError(format!(
+                        "'{stmt}': the range has to be aligned to the line size \
+                        and the line size a multiple of the instruction size"
+                    )));
+                }
+                Op::Loop { block, .. } | Op::Tile { block, .. } | Op::Interrupt { block, .. } => {
                     queue.extend(block.ops.iter());
                 }
                 Op::Switch { cases } => {
@@ -82,313 +230,2294 @@ impl<'a> TryFrom<&'a str> for TraceFile<'a> {
             }
         }
 
+        if let Some(cycle) = find_call_cycle(&definition_order, &block_map) {
+            return Err(TraceParseError::SyntaxError(format!(
+                "recursive call '{}'",
+                cycle.join("' -> '")
+            )));
+        }
+
+        for name in &definition_order {
+            check_unbounded_loops(&block_map[name].ops, &block_map)?;
+            address_end(&block_map[name].ops, &block_map).map_err(|op| {
+                TraceParseError::SyntaxError(format!(
+                    "'{op}' in '{name}' moves addresses past the end of the address space"
+                ))
+            })?;
+        }
+
+        if let Some(name) = preamble {
+            let Some(block) = block_map.get(name) else {
+                return Err(TraceParseError::SyntaxError(format!(
+                    "unknown preamble '{name}'"
+                )));
+            };
+
+            let mut unbounded = false;
+            ops_stats(&block.ops, &block_map, &mut Vec::new(), &mut unbounded);
+            if unbounded {
+                return Err(TraceParseError::SyntaxError(format!(
+                    "preamble '{name}' contains 'loop(inf)'"
+                )));
+            }
+        }
+
         Ok(Self {
             named_blocks: block_map,
+            definition_order,
+            metadata,
+            expected_instructions,
+            measured_miss_rates,
+            preamble,
+            unreachable_warnings,
         })
     }
 }
 
-impl<'a> IntoIterator for TraceFile<'a> {
-    type Item = (&'a str, std::vec::IntoIter<Instruction>);
-    type IntoIter = std::vec::IntoIter<Self::Item>;
-
-    fn into_iter(self) -> Self::IntoIter {
-        fn block_iter<'a>(
-            block: &NamedBlock<'a>,
-            block_map: &HashMap<&'a str, NamedBlock<'a>>,
-        ) -> std::vec::IntoIter<Instruction> {
-            let mut rng: StdRng = StdRng::seed_from_u64(0);
-            let mut addresses = Vec::new();
-
-            let mut queue = Vec::<&Op<'a>>::from_iter(block.ops.iter().rev());
-            while let Some(op) = queue.pop() {
-                match op {
-                    Op::Range {
-                        addr_start,
-                        instr_length,
-                        addr_end,
-                    } => addresses.extend((*addr_start..*addr_end).step_by(*instr_length / 8).map(
-                        |address| Instruction {
-                            address,
-                            length: *instr_length,
-                        },
-                    )),
-                    Op::BlockCall { block_name } => {
-                        queue.extend(block_map.get(block_name).unwrap().ops.iter().rev());
-                    }
-                    Op::Loop { count, block } => {
-                        for _ in 0..*count {
-                            queue.extend(block.ops.iter().rev());
-                        }
-                    }
-                    Op::Switch { cases } => {
-                        let mut weights: Vec<(usize, usize)> = cases
-                            .iter()
-                            .enumerate()
-                            .map(|(i, case)| (i, case.weight))
-                            .collect();
-                        weights.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
-
-                        let total_weights = weights.iter().map(|(_, weight)| weight).sum();
-                        let random = rng.random_range(0..=total_weights);
-
-                        let mut sum = 0;
-                        for (i, weight) in weights {
-                            sum += weight;
-                            if sum >= random {
-                                queue.extend(cases.get(i).unwrap().block.ops.iter().rev());
-                                break;
-                            }
-                        }
+/// the blocks of the first call or tailcall cycle, starting and ending with the same block
+fn find_call_cycle<'a>(
+    definition_order: &[&'a str],
+    block_map: &HashMap<&'a str, NamedBlock<'a>>,
+) -> Option<Vec<&'a str>> {
+    fn callees<'a>(ops: &[Op<'a>], out: &mut Vec<&'a str>) {
+        for op in ops {
+            match op {
+                Op::BlockCall { block_name } | Op::TailCall { block_name } => out.push(block_name),
+                Op::Loop { block, .. } | Op::Tile { block, .. } | Op::Interrupt { block, .. } => {
+                    callees(&block.ops, out)
+                }
+                Op::Switch { cases } => {
+                    for case in cases {
+                        callees(&case.block.ops, out);
                     }
                 }
+                _ => {}
             }
+        }
+    }
 
-            addresses.into_iter()
+    // blocks that are done never lead back to a block on the stack
+    let mut done = HashSet::new();
+    for &root in definition_order {
+        if done.contains(root) {
+            continue;
         }
 
-        self.named_blocks
-            .values()
-            .filter(|&block| block.compare)
-            .map(|block| (block.name, block_iter(block, &self.named_blocks)))
-            .collect::<Vec<_>>()
-            .into_iter()
+        // iterative depth first search, `stack` is the current call path
+        let mut stack: Vec<(&'a str, Vec<&'a str>)> = Vec::new();
+        let mut root_callees = Vec::new();
+        callees(&block_map[root].ops, &mut root_callees);
+        stack.push((root, root_callees));
+        while let Some((_, pending)) = stack.last_mut() {
+            let Some(callee) = pending.pop() else {
+                let (name, _) = stack.pop().unwrap();
+                done.insert(name);
+                continue;
+            };
+
+            if let Some(position) = stack.iter().position(|(name, _)| *name == callee) {
+                let mut cycle: Vec<&str> =
+                    stack[position..].iter().map(|(name, _)| *name).collect();
+                cycle.push(callee);
+                return Some(cycle);
+            }
+            if !done.contains(callee) {
+                let mut next = Vec::new();
+                callees(&block_map[callee].ops, &mut next);
+                stack.push((callee, next));
+            }
+        }
     }
-}
 
-pub struct Instruction {
-    pub address: usize,
-    pub length: usize,
+    None
 }
 
-#[derive(Debug, Clone, PartialEq)]
-struct NamedBlock<'a> {
-    compare: bool,
-    name: &'a str,
-    ops: Vec<Op<'a>>,
-}
+/// a `loop(inf)` without a break probability whose body has no instructions
+/// would never reach the instruction limit
+fn check_unbounded_loops<'a>(
+    ops: &[Op<'a>],
+    block_map: &HashMap<&'a str, NamedBlock<'a>>,
+) -> Result<(), TraceParseError<'a>> {
+    for op in ops {
+        match op {
+            Op::Loop {
+                count: None,
+                block,
+                break_probability,
+            } if break_probability.is_none_or(|p| p == 0.0)
+                && body_stats(&block.ops, block_map, &mut Vec::new(), &mut false) == (0, false) =>
+            {
+                return Err(TraceParseError::SyntaxError(String::from(
+                    "'loop(inf)' without instructions never ends",
+                )));
+            }
+            Op::Loop { block, .. } | Op::Tile { block, .. } | Op::Interrupt { block, .. } => {
+                check_unbounded_loops(&block.ops, block_map)?;
+            }
+            Op::Switch { cases } => {
+                for case in cases {
+                    check_unbounded_loops(&case.block.ops, block_map)?;
+                }
+            }
+            _ => {}
+        }
+    }
 
-#[derive(Debug, Clone, PartialEq)]
-struct Block<'a> {
-    ops: Vec<Op<'a>>,
+    Ok(())
 }
 
-#[derive(Debug, Clone, PartialEq)]
-enum Op<'a> {
-    Range {
-        addr_start: usize,
-        instr_length: usize,
-        addr_end: usize,
-    },
-    BlockCall {
-        block_name: &'a str,
-    },
-    Loop {
-        count: usize,
-        block: Block<'a>,
-    },
-    Switch {
-        cases: Vec<SwitchCase<'a>>,
-    },
-}
+/// the largest address or range end in `ops` after adding the offsets of tiles and strides,
+/// 0 if they access no addresses
+///
+/// `Err` is the tile or stride whose offsets overflow, the called blocks must not be recursive
+fn address_end<'a>(
+    ops: &[Op<'a>],
+    block_map: &HashMap<&'a str, NamedBlock<'a>>,
+) -> Result<usize, String> {
+    let mut end = 0;
+    for op in ops {
+        let op_end = match op {
+            Op::Range { addr_end, .. }
+            | Op::Random { addr_end, .. }
+            | Op::Zipf { addr_end, .. } => *addr_end,
+            Op::FlushLine { address } | Op::DataAccess { address, .. } => *address,
+            Op::Stride { start, count, step } => count
+                .saturating_sub(1)
+                .checked_mul(*step)
+                .and_then(|offset| offset.checked_add(*start))
+                .ok_or_else(|| op.to_string())?,
+            Op::BlockCall { block_name } | Op::TailCall { block_name } => {
+                address_end(&block_map[block_name].ops, block_map)?
+            }
+            Op::Loop { block, .. } | Op::Interrupt { block, .. } => {
+                address_end(&block.ops, block_map)?
+            }
+            Op::Tile {
+                count,
+                stride,
+                block,
+            } => {
+                let block_end = address_end(&block.ops, block_map)?;
+                count
+                    .saturating_sub(1)
+                    .checked_mul(*stride)
+                    .and_then(|offset| offset.checked_add(block_end))
+                    .ok_or_else(|| format!("tile({count}, {stride:#X})"))?
+            }
+            Op::Switch { cases } => {
+                let mut cases_end = 0;
+                for case in cases {
+                    cases_end = cases_end.max(address_end(&case.block.ops, block_map)?);
+                }
+                cases_end
+            }
+            Op::Client { .. }
+            | Op::InstrLength { .. }
+            | Op::Jump { .. }
+            | Op::UnresolvedRange { .. }
+            | Op::UnresolvedRandom { .. } => 0,
+        };
+        end = end.max(op_end);
+    }
 
-#[derive(Debug, Clone, PartialEq)]
-struct SwitchCase<'a> {
-    weight: usize,
-    block: Block<'a>,
+    Ok(end)
 }
 
-fn block_name<'a>(input: &mut &'a str) -> ModalResult<&'a str> {
-    take_while(1.., (AsChar::is_alphanum, '_', '-')).parse_next(input)
+fn nesting_depth(ops: &[Op<'_>]) -> usize {
+    ops.iter()
+        .map(|op| match op {
+            Op::Loop { block, .. } | Op::Tile { block, .. } | Op::Interrupt { block, .. } => {
+                1 + nesting_depth(&block.ops)
+            }
+            Op::Switch { cases } => {
+                1 + cases
+                    .iter()
+                    .map(|case| nesting_depth(&case.block.ops))
+                    .max()
+                    .unwrap_or(0)
+            }
+            _ => 0,
+        })
+        .max()
+        .unwrap_or(0)
 }
 
-fn named_block<'a>(input: &mut &'a str) -> ModalResult<NamedBlock<'a>> {
-    (
-        opt(terminated("compare", space1)).map(|cmp| cmp.is_some()),
-        delimited('\'', cut_err(block_name), cut_err('\'')),
-        cut_err(block),
-    )
-        .map(|(compare, name, Block { ops })| NamedBlock { compare, name, ops })
-        .parse_next(input)
+/// removes the ops following a `tailcall` in the same body, control never returns to them
+fn drop_unreachable(ops: &mut Vec<Op<'_>>, block_name: &str, warnings: &mut Vec<String>) {
+    if let Some(position) = ops.iter().position(|op| matches!(op, Op::TailCall { .. })) {
+        let unreachable = ops.len() - position - 1;
+        if unreachable > 0 {
+            warnings.push(format!(
+                "{unreachable} op(s) after '{}' in '{block_name}' are unreachable and were dropped",
+                ops[position]
+            ));
+            ops.truncate(position + 1);
+        }
+    }
+
+    for op in ops {
+        match op {
+            Op::Loop { block, .. } | Op::Tile { block, .. } | Op::Interrupt { block, .. } => {
+                drop_unreachable(&mut block.ops, block_name, warnings);
+            }
+            Op::Switch { cases } => {
+                for case in cases {
+                    drop_unreachable(&mut case.block.ops, block_name, warnings);
+                }
+            }
+            _ => {}
+        }
+    }
 }
 
-fn block<'a>(input: &mut &'a str) -> ModalResult<Block<'a>> {
-    delimited(
-        (multispace, '{').context(StrContext::Label("block start")),
-        cut_err(
-            repeat_till(
-                0..,
-                op,
-                (multispace, '}').context(StrContext::Label("block end")),
-            )
-            .map(|(ops, _)| Block { ops }),
-        ),
-        end,
-    )
-    .parse_next(input)
+/// records every pair of ranges in the same body whose addresses intersect
+fn find_overlaps(ops: &[Op<'_>], block_name: &str, warnings: &mut Vec<String>) {
+    let ranges: Vec<(usize, usize, &Op<'_>)> = ops
+        .iter()
+        .filter_map(|op| match op {
+            Op::Range {
+                addr_start,
+                addr_end,
+                ..
+            } => Some((*addr_start, *addr_end, op)),
+            _ => None,
+        })
+        .collect();
+    for (i, &(start_a, end_a, first)) in ranges.iter().enumerate() {
+        for &(start_b, end_b, second) in &ranges[i + 1..] {
+            if start_a < end_b && start_b < end_a {
+                warnings.push(format!(
+                    "'{first}' and '{second}' in '{block_name}' overlap"
+                ));
+            }
+        }
+    }
+
+    for op in ops {
+        match op {
+            Op::Loop { block, .. } | Op::Tile { block, .. } | Op::Interrupt { block, .. } => {
+                find_overlaps(&block.ops, block_name, warnings);
+            }
+            Op::Switch { cases } => {
+                for case in cases {
+                    find_overlaps(&case.block.ops, block_name, warnings);
+                }
+            }
+            _ => {}
+        }
+    }
 }
 
-fn op<'a>(input: &mut &'a str) -> ModalResult<Op<'a>> {
-    // important: try 'range' before 'address' because of ambiguity
-    preceded(multispace, alt((block_call, looop, switch, range)))
-        .context(StrContext::Label("statement"))
-        .parse_next(input)
+/// the empty and comment lines before the first block
+fn header_lines(input: &str) -> impl Iterator<Item = &str> {
+    input
+        .lines()
+        .map(str::trim)
+        .take_while(|line| line.is_empty() || line.starts_with("//"))
 }
 
-fn range<'a>(input: &mut &'a str) -> ModalResult<Op<'a>> {
-    fn range_inner(input: &mut &str) -> ModalResult<(usize, usize, usize)> {
-        terminated((integer, delimited("..", integer, ".."), integer), end).parse_next(input)
-    }
+/// the arguments of `line` if it is the comment `directive`, e.g. `// @meta`,
+/// the directive has to be followed by whitespace or end the line
+fn directive_arguments<'a>(line: &'a str, directive: &str) -> Option<&'a str> {
+    line.strip_prefix(directive)
+        .filter(|arguments| arguments.is_empty() || arguments.starts_with(char::is_whitespace))
+}
 
-    let (addr_start, instr_length, addr_end) = peek(range_inner).parse_next(input)?;
+/// collects the `key=value` pairs of `// @meta` comments before the first block
+fn parse_metadata(input: &str) -> Result<HashMap<String, String>, TraceParseError<'_>> {
+    let mut metadata = HashMap::new();
+    for line in header_lines(input) {
+        let Some(entries) = directive_arguments(line, "// @meta") else {
+            continue;
+        };
 
-    if addr_start >= addr_end {
-        return fail
-            .context(StrContext::Label("range: range is empty"))
-            .parse_next(input)?;
+        for entry in entries.split_whitespace() {
+            let Some((key, value)) = entry.split_once('=') else {
+                return Err(TraceParseError::SyntaxError(format!(
+                    "metadata entry '{entry}' is not of the form key=value"
+                )));
+            };
+            metadata.insert(key.to_string(), value.to_string());
+        }
     }
 
-    if instr_length % 8 != 0 {
-        return fail
-            .context(StrContext::Label(
-                "range: instruction size is not a multiple of 8 (bits)",
-            ))
-            .parse_next(input)?;
-    }
+    Ok(metadata)
+}
 
-    if (addr_end - addr_start) % (instr_length / 8) != 0 {
-        return fail
-            .context(StrContext::Label(
-                "range: instruction size does not cleanly fit in range",
+/// the count of the last `// @expect_instructions` comment before the first block
+fn parse_expected_instructions(input: &str) -> Result<Option<usize>, TraceParseError<'_>> {
+    let mut expected_instructions = None;
+    for line in header_lines(input) {
+        let Some(count) = directive_arguments(line, "// @expect_instructions") else {
+            continue;
+        };
+
+        let count = count.trim();
+        expected_instructions = Some(count.parse().map_err(|_| {
+            TraceParseError::SyntaxError(format!(
+                "expected instruction count '{count}' is not a number"
             ))
-            .parse_next(input)?;
+        })?);
     }
 
-    range_inner
-        .parse_next(input)
-        .map(|(addr_start, instr_length, addr_end)| Op::Range {
-            addr_start,
-            instr_length,
-            addr_end,
-        })
+    Ok(expected_instructions)
 }
 
-fn block_call<'a>(input: &mut &'a str) -> ModalResult<Op<'a>> {
-    terminated(block_name, ("()", end))
-        .map(|function_name| Op::BlockCall {
-            block_name: function_name,
+/// splits the text of a top level item into the item and the empty and comment lines following it
+fn split_trailing_comments(text: &str) -> (&str, &str) {
+    let trailing: usize = text
+        .split_inclusive('\n')
+        .rev()
+        .take_while(|line| {
+            let line = line.trim();
+            line.is_empty() || line.starts_with("//")
         })
-        .parse_next(input)
-        .map_err(|e| e.backtrack()) // remove cut_err from function_name
-}
+        .map(str::len)
+        .sum();
 
-fn looop<'a>(input: &mut &'a str) -> ModalResult<Op<'a>> {
-    preceded(
-        "loop",
-        cut_err((
-            delimited((space, '(', space), decimal_integer, (space, ')', space))
-                .context(StrContext::Label("loop count")),
-            block,
-        ))
-        .map(|(count, block)| Op::Loop { count, block }),
-    )
-    .parse_next(input)
+    text.split_at(text.len() - trailing)
 }
 
-fn switch<'a>(input: &mut &'a str) -> ModalResult<Op<'a>> {
-    preceded(
-        ("switch:", cut_err(end)),
-        cut_err(terminated(
-            repeat_till(
-                1..,
-                switch_case,
-                (multispace, "endswitch").context(StrContext::Label("switch end")),
-            )
-            .map(|(cases, _)| Op::Switch { cases }),
-            end,
-        )),
-    )
-    .parse_next(input)
-}
+/// the miss rate of the last `// @measured_miss_rate` comment in `input`
+fn parse_measured_miss_rate(input: &str) -> Result<Option<f64>, TraceParseError<'_>> {
+    let mut measured_miss_rate = None;
+    for line in input.lines().map(str::trim) {
+        let Some(miss_rate) = directive_arguments(line, "// @measured_miss_rate") else {
+            continue;
+        };
 
-fn switch_case<'a>(input: &mut &'a str) -> ModalResult<SwitchCase<'a>> {
-    separated_pair(
-        delimited((space, '(', space), decimal_integer, (space, ')', space)),
-        (space, ':', space),
-        block,
-    )
-    .map(|(weight, block)| SwitchCase { weight, block })
-    .context(StrContext::Label("switch case"))
-    .parse_next(input)
-}
+        let miss_rate = miss_rate.trim();
+        measured_miss_rate = Some(
+            miss_rate
+                .parse()
+                .ok()
+                .filter(|miss_rate| (0.0..=1.0).contains(miss_rate))
+                .ok_or_else(|| {
+                    TraceParseError::SyntaxError(format!(
+                        "measured miss rate '{miss_rate}' is not a number between 0 and 1"
+                    ))
+                })?,
+        );
+    }
 
-fn integer(input: &mut &str) -> ModalResult<usize> {
-    alt((
-        preceded(
-            "0x",
-            cut_err(take_while(1.., ('0'..='9', 'a'..='f', 'A'..='F')))
-                .try_map(|s| usize::from_str_radix(s, 16))
-                .context(StrContext::Label("hexadecimal number")),
-        ),
-        preceded(
-            "0b",
-            cut_err(take_while(1.., '0'..='1'))
-                .try_map(|s| usize::from_str_radix(s, 2))
-                .context(StrContext::Label("binary number")),
-        ),
-        preceded(
-            "0o",
-            cut_err(take_while(1.., '0'..='7'))
-                .try_map(|s| usize::from_str_radix(s, 8))
-                .context(StrContext::Label("octal number")),
-        ),
-        cut_err(decimal_integer),
-    ))
-    .parse_next(input)
+    Ok(measured_miss_rate)
 }
 
-fn decimal_integer(input: &mut &str) -> ModalResult<usize> {
-    take_while(1.., '0'..='9')
-        .context(StrContext::Label("decimal number"))
-        .try_map(str::parse::<usize>)
-        .parse_next(input)
-}
+/// replaces each [`Op::UnresolvedRange`] with an [`Op::Range`]
+/// and each [`Op::UnresolvedRandom`] with an [`Op::Random`]
+///
+/// a missing instruction size is taken from the closest preceding `instr_length`
+/// in the same or an enclosing block
+///
+/// relative addresses are applied to the cursor, which is the end of the previous range in source order
+/// (starting at 0 for every named block). after a switch the cursor is the furthest end of all cases,
+/// block calls, random and zipf don't move the cursor
+///
+/// each [`Op::Jump`] becomes a single instruction at the cursor and moves the cursor to its target.
+/// after a tile the cursor is where its last repetition ended
+fn resolve_ranges<'a>(
+    ops: &mut [Op<'a>],
+    mut instr_length: Option<usize>,
+    cursor: &mut usize,
+) -> Result<(), TraceParseError<'a>> {
+    for op in ops {
+        match op {
+            Op::InstrLength {
+                instr_length: length,
+            } => instr_length = Some(*length),
+            Op::Range { addr_end, .. } => *cursor = *addr_end,
+            Op::UnresolvedRange {
+                addr_start,
+                instr_length: length,
+                addr_end,
+            } => {
+                let range = format!("{addr_start}..{addr_end}");
+                let resolve = |address: &Address| match address {
+                    Address::Absolute(address) => Some(*address),
+                    Address::Forward(offset) => cursor.checked_add(*offset),
+                    Address::Backward(offset) => cursor.checked_sub(*offset),
+                };
+                let (Some(start), Some(end)) = (resolve(addr_start), resolve(addr_end)) else {
+                    return Err(TraceParseError::SyntaxError(format!(
+                        "range {range} moves the cursor {:#X} out of the address space",
+                        *cursor
+                    )));
+                };
 
-fn end(input: &mut &str) -> ModalResult<()> {
-    (space, alt((line_ending, eof)), multispace)
-        .void()
-        .context(StrContext::Label("newline"))
-        .parse_next(input)
-}
+                let Some(instr_length) = length.or(instr_length) else {
+                    return Err(TraceParseError::SyntaxError(format!(
+                        "range {range} has no instruction size and there is no preceding 'instr_length'"
+                    )));
+                };
 
-fn space(input: &mut &str) -> ModalResult<()> {
-    (space0, opt(("//", till_line_ending)), space0)
-        .void()
-        .context(StrContext::Label("newline"))
-        .parse_next(input)
-}
+                if start >= end {
+                    return Err(TraceParseError::SyntaxError(format!(
+                        "range {range}: range {start:#X}..{end:#X} is empty"
+                    )));
+                }
 
-fn multispace(input: &mut &str) -> ModalResult<()> {
-    (
-        multispace0,
-        repeat::<_, _, (), _, _>(0.., ("//", till_line_ending, multispace0)),
-        multispace0,
-    )
-        .void()
-        .context(StrContext::Label("newline"))
-        .parse_next(input)
-}
+                if instr_length == 0 {
+                    return Err(TraceParseError::SyntaxError(format!(
+                        "range {range}: instruction size is zero"
+                    )));
+                }
 
-#[cfg(test)]
-mod test {
-    use super::TraceFile;
+                if !((end - start) * 8).is_multiple_of(instr_length) {
+                    return Err(TraceParseError::SyntaxError(format!(
+                        "range {range}: instruction size {instr_length} does not cleanly fit in range"
+                    )));
+                }
+
+                *cursor = end;
+                *op = Op::Range {
+                    addr_start: start,
+                    instr_length,
+                    addr_end: end,
+                };
+            }
+            Op::UnresolvedRandom {
+                count,
+                addr_start,
+                addr_end,
+            } => {
+                let (count, addr_start, addr_end) = (*count, *addr_start, *addr_end);
+                let random = op.to_string();
+                let Some(instr_length) = instr_length else {
+                    return Err(TraceParseError::SyntaxError(format!(
+                        "{random} has no instruction size and there is no preceding 'instr_length'"
+                    )));
+                };
+
+                if !((addr_end - addr_start) * 8).is_multiple_of(instr_length) {
+                    return Err(TraceParseError::SyntaxError(format!(
+                        "{random}: instruction size {instr_length} does not cleanly fit in range"
+                    )));
+                }
+
+                *op = Op::Random {
+                    count,
+                    addr_start,
+                    instr_length,
+                    addr_end,
+                };
+            }
+            Op::Jump { target } => {
+                let Some(instr_length) = instr_length else {
+                    return Err(TraceParseError::SyntaxError(format!(
+                        "jump {target:#X} has no instruction size and there is no preceding 'instr_length'"
+                    )));
+                };
+
+                let addr_start = *cursor;
+                *cursor = *target;
+                *op = Op::Range {
+                    addr_start,
+                    instr_length,
+                    addr_end: addr_start + instr_length.div_ceil(8),
+                };
+            }
+            Op::Loop { block, .. } => resolve_ranges(&mut block.ops, instr_length, cursor)?,
+            Op::Tile {
+                count,
+                stride,
+                block,
+            } => {
+                let start = *cursor;
+                resolve_ranges(&mut block.ops, instr_length, cursor)?;
+
+                // the cursor continues after the last repetition
+                *cursor = match count {
+                    0 => Some(start),
+                    _ => (*count - 1)
+                        .checked_mul(*stride)
+                        .and_then(|offset| cursor.checked_add(offset)),
+                }
+                .ok_or_else(|| {
+                    TraceParseError::SyntaxError(format!(
+                        "tile({count}, {stride:#X}) moves the cursor out of the address space"
+                    ))
+                })?;
+            }
+            // the service routine does not move the cursor of the surrounding block
+            Op::Interrupt { block, .. } => {
+                let mut isr_cursor = *cursor;
+                resolve_ranges(&mut block.ops, instr_length, &mut isr_cursor)?;
+            }
+            Op::Switch { cases } => {
+                let start = *cursor;
+                for case in cases {
+                    let mut case_cursor = start;
+                    resolve_ranges(&mut case.block.ops, instr_length, &mut case_cursor)?;
+                    *cursor = (*cursor).max(case_cursor);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// statically derived properties of a compare block, no instructions are generated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticStats {
+    /// for switches the case with the most instructions is counted and loops with a `break`
+    /// count all iterations, instructions injected by interrupts are not counted
+    pub instruction_count: usize,
+    /// merged and sorted address ranges the block can access
+    pub address_ranges: Vec<Range<usize>>,
+    /// whether the block can reach a `loop(inf)` and only terminates with an instruction limit
+    pub unbounded: bool,
+}
+
+impl<'a> TraceFile<'a> {
+    /// a single compare block `perf` from the output of `perf script -F ip`
+    ///
+    /// every line starts with the hexadecimal instruction pointer, following symbol columns are ignored.
+    /// all instructions are assumed to be `instr_length` bits long
+    pub fn from_perf_script(
+        text: &'a str,
+        instr_length: usize,
+    ) -> Result<Self, TraceParseError<'a>> {
+        if instr_length == 0 || !instr_length.is_multiple_of(8) {
+            return Err(TraceParseError::SyntaxError(String::from(
+                "instruction size is not a multiple of 8 (bits)",
+            )));
+        }
+
+        let mut ops: Vec<Op<'a>> = Vec::new();
+        for (line_number, line) in text.lines().enumerate() {
+            let Some(ip) = line.split_whitespace().next() else {
+                continue;
+            };
+
+            let address = usize::from_str_radix(ip.trim_start_matches("0x"), 16).map_err(|_| {
+                TraceParseError::SyntaxError(format!(
+                    "line {}: '{ip}' is not an instruction pointer",
+                    line_number + 1
+                ))
+            })?;
+            let end = address.checked_add(instr_length / 8).ok_or_else(|| {
+                TraceParseError::SyntaxError(format!(
+                    "line {}: instruction at '{ip}' ends past the end of the address space",
+                    line_number + 1
+                ))
+            })?;
+
+            // sequential instructions are merged into a single range
+            match ops.last_mut() {
+                Some(Op::Range { addr_end, .. }) if *addr_end == address => *addr_end = end,
+                _ => ops.push(Op::Range {
+                    addr_start: address,
+                    instr_length,
+                    addr_end: end,
+                }),
+            }
+        }
+
+        let name = "perf";
+        Ok(Self {
+            named_blocks: HashMap::from([(
+                name,
+                NamedBlock {
+                    compare: true,
+                    name,
+                    ops,
+                },
+            )]),
+            definition_order: vec![name],
+            metadata: HashMap::new(),
+            expected_instructions: None,
+            measured_miss_rates: HashMap::new(),
+            preamble: None,
+            unreachable_warnings: Vec::new(),
+        })
+    }
+
+    /// like [`IntoIterator::into_iter`] but the n-th switch encountered while expanding a block
+    /// takes the case `choices[n % choices.len()] % cases.len()` instead of a random one
+    ///
+    /// an empty `choices` falls back to the random selection
+    pub fn into_iter_with_choices(
+        self,
+        choices: &[usize],
+    ) -> std::vec::IntoIter<(&'a str, std::vec::IntoIter<Instruction>)> {
+        self.expand(choices, usize::MAX)
+    }
+
+    /// like [`IntoIterator::into_iter`] but every block stops after `limit` instructions
+    ///
+    /// this is the only way to expand blocks containing a `loop(inf)`
+    pub fn into_iter_limited(
+        self,
+        limit: usize,
+    ) -> std::vec::IntoIter<(&'a str, std::vec::IntoIter<Instruction>)> {
+        self.expand(&[], limit)
+    }
+
+    /// the instructions of all compare blocks in definition order as a single stream,
+    /// each tagged with the name of its block
+    pub fn into_flat_iter(self) -> impl Iterator<Item = (&'a str, Instruction)> {
+        self.into_iter()
+            .flat_map(|(name, block)| block.map(move |instruction| (name, instruction)))
+    }
+
+    fn expand(
+        self,
+        choices: &[usize],
+        limit: usize,
+    ) -> std::vec::IntoIter<(&'a str, std::vec::IntoIter<Instruction>)> {
+        self.compare_blocks()
+            .map(|block| {
+                (
+                    block.name,
+                    block_iter(block, &self.named_blocks, choices, limit),
+                )
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// like [`TraceFile::into_iter_limited`] but the streams include the cache flushes and data accesses
+    pub fn into_events(
+        self,
+        limit: usize,
+    ) -> std::vec::IntoIter<(&'a str, std::vec::IntoIter<TraceEvent>)> {
+        self.into_events_seeded(limit, 0)
+    }
+
+    /// like [`TraceFile::into_events`] but switches, `random` and `zipf` draw from an rng seeded with `seed`
+    pub fn into_events_seeded(
+        self,
+        limit: usize,
+        seed: u64,
+    ) -> std::vec::IntoIter<(&'a str, std::vec::IntoIter<TraceEvent>)> {
+        self.compare_blocks()
+            .map(|block| {
+                (
+                    block.name,
+                    block_events(block, &self.named_blocks, &[], limit, seed),
+                )
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// like [`TraceFile::trace_named`] but the stream includes the cache flushes and data accesses
+    pub fn events_named(&self, name: &str) -> Option<std::vec::IntoIter<TraceEvent>> {
+        self.named_blocks
+            .get(name)
+            .filter(|block| block.compare)
+            .map(|block| block_events(block, &self.named_blocks, &[], usize::MAX, 0))
+    }
+
+    /// total number of instructions of all compare blocks given by a
+    /// `// @expect_instructions` comment at the start of the file
+    pub fn expected_instructions(&self) -> Option<usize> {
+        self.expected_instructions
+    }
+
+    /// per block the miss rate between 0 and 1 measured on real hardware, given by a
+    /// `// @measured_miss_rate` comment inside the block or just before it
+    pub fn measured_miss_rates(&self) -> &HashMap<&'a str, f64> {
+        &self.measured_miss_rates
+    }
+
+    /// the stream of the `preamble` block, simulated before every compare block without being counted
+    pub fn preamble(&self) -> Option<std::vec::IntoIter<TraceEvent>> {
+        self.preamble.map(|name| {
+            block_events(
+                self.named_blocks.get(name).unwrap(),
+                &self.named_blocks,
+                &[],
+                usize::MAX,
+                0,
+            )
+        })
+    }
+
+    /// one message per body whose ops after a `tailcall` were dropped
+    pub fn unreachable_warnings(&self) -> &[String] {
+        &self.unreachable_warnings
+    }
+
+    /// one message per pair of ranges in the same body that share addresses
+    ///
+    /// this may be intentional, e.g. a backward jump, so it is a lint and not an error.
+    /// all pairs of ranges are compared, so this is computed on demand and not while parsing
+    pub fn overlapping_ranges(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for name in &self.definition_order {
+            find_overlaps(&self.named_blocks[name].ops, name, &mut warnings);
+        }
+
+        warnings
+    }
+
+    /// the deepest nesting of loops, tiles, interrupts and switches in any block,
+    /// a block without any of them has a depth of 0. calls do not add to the depth
+    pub fn max_nesting_depth(&self) -> usize {
+        self.named_blocks
+            .values()
+            .map(|block| nesting_depth(&block.ops))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// whether the file defines no blocks at all
+    pub fn is_empty(&self) -> bool {
+        self.named_blocks.is_empty()
+    }
+
+    /// `key=value` pairs of the `// @meta` comments at the start of the file
+    pub fn metadata(&self) -> &HashMap<String, String> {
+        &self.metadata
+    }
+
+    /// the `compare` blocks in definition order
+    fn compare_blocks(&self) -> impl Iterator<Item = &NamedBlock<'a>> {
+        self.definition_order
+            .iter()
+            .map(|name| self.named_blocks.get(name).unwrap())
+            .filter(|block| block.compare)
+    }
+
+    /// whether the file defines a `compare` block with this name
+    pub fn contains_trace_named(&self, name: &str) -> bool {
+        self.named_blocks
+            .get(name)
+            .is_some_and(|block| block.compare)
+    }
+
+    /// the instructions of the `compare` block with this name
+    pub fn trace_named(&self, name: &str) -> Option<std::vec::IntoIter<Instruction>> {
+        self.named_blocks
+            .get(name)
+            .filter(|block| block.compare)
+            .map(|block| block_iter(block, &self.named_blocks, &[], usize::MAX))
+    }
+
+    /// a warning for every loop whose expansion exceeds `cap` instructions, `loop(inf)` is not reported
+    pub fn loop_warnings(&self, cap: usize) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for name in &self.definition_order {
+            let mut queue = Vec::from_iter(self.named_blocks.get(name).unwrap().ops.iter());
+            while let Some(op) = queue.pop() {
+                match op {
+                    Op::Loop { count, block, .. } => {
+                        if let Some(count) = count {
+                            let instructions = count.saturating_mul(ops_stats(
+                                &block.ops,
+                                &self.named_blocks,
+                                &mut Vec::new(),
+                                &mut false,
+                            ));
+                            if instructions > cap {
+                                warnings.push(format!(
+                                    "loop({count}) in '{name}' expands to {instructions} instructions, more than {cap}"
+                                ));
+                            }
+                        }
+                        queue.extend(block.ops.iter());
+                    }
+                    Op::Tile { block, .. } | Op::Interrupt { block, .. } => {
+                        queue.extend(block.ops.iter())
+                    }
+                    Op::Switch { cases } => {
+                        for case in cases {
+                            queue.extend(case.block.ops.iter());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        warnings
+    }
+
+    pub fn static_stats(&self) -> Vec<(&'a str, StaticStats)> {
+        self.compare_blocks()
+            .map(|block| {
+                let mut address_ranges = Vec::new();
+                let mut unbounded = false;
+                let instruction_count = ops_stats(
+                    &block.ops,
+                    &self.named_blocks,
+                    &mut address_ranges,
+                    &mut unbounded,
+                );
+
+                address_ranges.sort_by_key(|range| range.start);
+                let mut merged: Vec<Range<usize>> = Vec::new();
+                for range in address_ranges {
+                    match merged.last_mut() {
+                        Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                        _ => merged.push(range),
+                    }
+                }
+
+                (
+                    block.name,
+                    StaticStats {
+                        instruction_count,
+                        address_ranges: merged,
+                        unbounded,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+/// instruction count of `ops` as reported by [`TraceFile::static_stats`]
+fn ops_stats<'a>(
+    ops: &[Op<'a>],
+    block_map: &HashMap<&'a str, NamedBlock<'a>>,
+    address_ranges: &mut Vec<Range<usize>>,
+    unbounded: &mut bool,
+) -> usize {
+    body_stats(ops, block_map, address_ranges, unbounded).0
+}
+
+/// like [`ops_stats`] but also returns whether `ops` always end in a `tailcall`,
+/// which leaves the enclosing named block, so the following ops and iterations never run
+fn body_stats<'a>(
+    ops: &[Op<'a>],
+    block_map: &HashMap<&'a str, NamedBlock<'a>>,
+    address_ranges: &mut Vec<Range<usize>>,
+    unbounded: &mut bool,
+) -> (usize, bool) {
+    let mut instruction_count = 0usize;
+    for op in ops {
+        let (count, tail_called) = match op {
+            Op::Range {
+                addr_start,
+                instr_length,
+                addr_end,
+            } => {
+                address_ranges.push(*addr_start..*addr_end);
+                ((*addr_end - *addr_start) * 8 / *instr_length, false)
+            }
+            Op::Random {
+                count,
+                addr_start,
+                addr_end,
+                ..
+            }
+            | Op::Zipf {
+                count,
+                addr_start,
+                addr_end,
+                ..
+            } => {
+                address_ranges.push(*addr_start..*addr_end);
+                (*count, false)
+            }
+            Op::BlockCall { block_name } => (
+                ops_stats(
+                    &block_map.get(block_name).unwrap().ops,
+                    block_map,
+                    address_ranges,
+                    unbounded,
+                ),
+                false,
+            ),
+            Op::TailCall { block_name } => (
+                ops_stats(
+                    &block_map.get(block_name).unwrap().ops,
+                    block_map,
+                    address_ranges,
+                    unbounded,
+                ),
+                true,
+            ),
+            Op::Loop {
+                count,
+                block,
+                break_probability,
+            } => match body_stats(&block.ops, block_map, address_ranges, unbounded) {
+                // the first iteration leaves the loop
+                (instruction_count, true) => (instruction_count, true),
+                (instruction_count, false) => {
+                    *unbounded |= count.is_none() && break_probability.is_none_or(|p| p == 0.0);
+                    // an unbounded loop saturates the count unless its body is empty
+                    (
+                        count
+                            .unwrap_or(usize::MAX)
+                            .saturating_mul(instruction_count),
+                        false,
+                    )
+                }
+            },
+            Op::Tile {
+                count,
+                stride,
+                block,
+            } => {
+                let mut tile_ranges = Vec::new();
+                let (instruction_count, tail_called) =
+                    body_stats(&block.ops, block_map, &mut tile_ranges, unbounded);
+                let repetitions = if tail_called { (*count).min(1) } else { *count };
+                for i in 0..repetitions {
+                    address_ranges.extend(
+                        tile_ranges
+                            .iter()
+                            .map(|range| range.start + i * stride..range.end + i * stride),
+                    );
+                }
+                (
+                    repetitions.saturating_mul(instruction_count),
+                    tail_called && repetitions > 0,
+                )
+            }
+            Op::Interrupt { block, .. } => {
+                ops_stats(&block.ops, block_map, address_ranges, unbounded);
+                (0, false)
+            }
+            Op::Switch { cases } => cases
+                .iter()
+                .map(|case| body_stats(&case.block.ops, block_map, address_ranges, unbounded))
+                .fold(
+                    (0, !cases.is_empty()),
+                    |(max, all), (count, tail_called)| (max.max(count), all && tail_called),
+                ),
+            Op::InstrLength { .. }
+            | Op::FlushLine { .. }
+            | Op::DataAccess { .. }
+            | Op::Stride { .. }
+            | Op::Client { .. } => (0, false),
+            Op::Jump { .. } | Op::UnresolvedRange { .. } | Op::UnresolvedRandom { .. } => {
+                unreachable!("resolved while parsing")
+            }
+        };
+
+        instruction_count = instruction_count.saturating_add(count);
+        if tail_called {
+            return (instruction_count, true);
+        }
+    }
+
+    (instruction_count, false)
+}
+
+impl<'a> IntoIterator for TraceFile<'a> {
+    type Item = (&'a str, std::vec::IntoIter<Instruction>);
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.into_iter_with_choices(&[])
+    }
+}
+
+/// first divergence between the instruction streams of two compare blocks with the same name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffEntry {
+    pub name: String,
+    /// index of the first instruction that differs
+    pub index: usize,
+    /// `None` if the stream of the block ended before `index` or the block doesn't exist in that file
+    pub address_a: Option<usize>,
+    pub address_b: Option<usize>,
+}
+
+/// expands the compare blocks of both files and reports the first divergence of every block whose
+/// instruction stream differs, sorted by name
+pub fn diff<'a>(file_a: &'a str, file_b: &'a str) -> Result<Vec<DiffEntry>, TraceParseError<'a>> {
+    let mut blocks_b: HashMap<&str, Vec<Instruction>> = TraceFile::try_from(file_b)?
+        .into_iter()
+        .map(|(name, block)| (name, block.collect()))
+        .collect();
+
+    let mut blocks: Vec<(&str, Vec<Instruction>, Vec<Instruction>)> = TraceFile::try_from(file_a)?
+        .into_iter()
+        .map(|(name, block)| {
+            let block_b = blocks_b.remove(name).unwrap_or_default();
+            (name, block.collect(), block_b)
+        })
+        .collect();
+    // compare blocks that only exist in `file_b`
+    blocks.extend(
+        blocks_b
+            .into_iter()
+            .map(|(name, block)| (name, Vec::new(), block)),
+    );
+
+    let mut entries: Vec<DiffEntry> = blocks
+        .into_iter()
+        .filter_map(|(name, block_a, block_b)| {
+            let index = (0..block_a.len().max(block_b.len()))
+                .find(|&i| block_a.get(i) != block_b.get(i))?;
+
+            Some(DiffEntry {
+                name: name.to_string(),
+                index,
+                address_a: block_a.get(index).map(|instruction| instruction.address),
+                address_b: block_b.get(index).map(|instruction| instruction.address),
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(entries)
+}
+
+/// generates a syntactically valid trace file for property tests
+///
+/// the file consists of a few helper blocks and `compare` blocks built from randomly nested
+/// ranges, loops, switches and calls. blocks only call blocks defined before them, so there is no recursion
+pub fn generate_random(seed: u64, max_depth: usize) -> String {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut out = String::new();
+
+    let helpers = rng.random_range(0..=3);
+    for index in 0..helpers {
+        out.push_str(&format!("'b{index}' {{\n"));
+        random_ops(&mut rng, max_depth, index, 1, &mut out);
+        out.push_str("}\n\n");
+    }
+
+    for index in 0..rng.random_range(1..=3) {
+        out.push_str(&format!("compare 'c{index}' {{\n"));
+        random_ops(&mut rng, max_depth, helpers, 1, &mut out);
+        out.push_str("}\n\n");
+    }
+
+    out
+}
+
+/// like [`generate_random`] but `compare` blocks are added until the file is at least `min_bytes` long,
+/// used to measure the parser on large inputs
+pub fn generate_large(seed: u64, min_bytes: usize) -> String {
+    const HELPERS: usize = 4;
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut out = String::new();
+
+    for index in 0..HELPERS {
+        out.push_str(&format!("'b{index}' {{\n"));
+        random_ops(&mut rng, 3, index, 1, &mut out);
+        out.push_str("}\n\n");
+    }
+
+    let mut index = 0;
+    while out.len() < min_bytes {
+        out.push_str(&format!("compare 'c{index}' {{\n"));
+        random_ops(&mut rng, 3, HELPERS, 1, &mut out);
+        out.push_str("}\n\n");
+        index += 1;
+    }
+
+    out
+}
+
+/// parses `input` `iterations` times (at least once) and returns the throughput in bytes per second
+///
+/// not available on wasm32 where [`std::time::Instant`] panics
+#[cfg(not(target_arch = "wasm32"))]
+pub fn parse_throughput(input: &str, iterations: usize) -> Result<f64, TraceParseError<'_>> {
+    let iterations = iterations.max(1);
+
+    let start = std::time::Instant::now();
+    for _ in 0..iterations {
+        std::hint::black_box(TraceFile::try_from(std::hint::black_box(input))?);
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    Ok((input.len() * iterations) as f64 / elapsed)
+}
+
+/// appends 1 to 3 random ops nested at most `depth` levels deep, calls go to the blocks `b0` to `b{callable - 1}`
+fn random_ops(rng: &mut StdRng, depth: usize, callable: usize, indent: usize, out: &mut String) {
+    let pad = "    ".repeat(indent);
+    for _ in 0..rng.random_range(1..=3) {
+        match rng.random_range(0..if depth == 0 { 2 } else { 4 }) {
+            0 => {
+                let instr_length = [16, 32, 64][rng.random_range(0..3)];
+                let addr_start = rng.random_range(0..0x1000) * 8;
+                let addr_end = addr_start + rng.random_range(1..=16) * instr_length / 8;
+                out.push_str(&format!(
+                    "{pad}{addr_start:#X}..{instr_length}..{addr_end:#X}\n"
+                ));
+            }
+            1 if callable > 0 => {
+                out.push_str(&format!("{pad}b{}()\n", rng.random_range(0..callable)));
+            }
+            1 => out.push_str(&format!("{pad}0x0..32..0x4\n")),
+            2 => {
+                out.push_str(&format!("{pad}loop({}) {{\n", rng.random_range(0..=3)));
+                random_ops(rng, depth - 1, callable, indent + 1, out);
+                out.push_str(&format!("{pad}}}\n"));
+            }
+            _ => {
+                out.push_str(&format!("{pad}switch:\n"));
+                for _ in 0..rng.random_range(1..=3) {
+                    out.push_str(&format!("{pad}    ({}): {{\n", rng.random_range(1..=5)));
+                    random_ops(rng, depth - 1, callable, indent + 2, out);
+                    out.push_str(&format!("{pad}    }}\n"));
+                }
+                out.push_str(&format!("{pad}endswitch\n"));
+            }
+        }
+    }
+}
+
+/// like [`block_events`] with only the instructions
+fn block_iter<'a>(
+    block: &NamedBlock<'a>,
+    block_map: &HashMap<&'a str, NamedBlock<'a>>,
+    choices: &[usize],
+    limit: usize,
+) -> std::vec::IntoIter<Instruction> {
+    block_events(block, block_map, choices, limit, 0)
+        .filter_map(|event| match event {
+            TraceEvent::Instruction(instruction) => Some(instruction),
+            TraceEvent::FlushLine(_)
+            | TraceEvent::Load(_)
+            | TraceEvent::Store(_)
+            | TraceEvent::Client(_) => None,
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// `choices` overrides the random case selection of switches, see [`TraceFile::into_iter_with_choices`]
+///
+/// expansion stops after `limit` instructions
+fn block_events<'a, 'b>(
+    block: &'b NamedBlock<'a>,
+    block_map: &'b HashMap<&'a str, NamedBlock<'a>>,
+    choices: &[usize],
+    limit: usize,
+    seed: u64,
+) -> std::vec::IntoIter<TraceEvent> {
+    let mut expansion = Expansion {
+        block_map,
+        choices,
+        rng: StdRng::seed_from_u64(seed),
+        switch_count: 0,
+    };
+    let mut interrupts = Vec::new();
+    let mut events = expansion.expand(&block.ops, 0, limit, &mut interrupts);
+
+    if !interrupts.is_empty() {
+        // (every, service routine, base, instructions since the last injection)
+        let mut active = Vec::new();
+        let mut pending = interrupts.into_iter().peekable();
+        let mut instructions = 0;
+        let mut interleaved = Vec::with_capacity(events.len());
+        for (position, event) in events.into_iter().enumerate() {
+            while let Some((_, every, ops, base)) =
+                pending.next_if(|(activated_at, ..)| *activated_at == position)
+            {
+                active.push((every, ops, base, 0));
+            }
+            if instructions >= limit {
+                break;
+            }
+
+            interleaved.push(event);
+            if let TraceEvent::Instruction(_) = event {
+                instructions += 1;
+                for (every, ops, base, since) in &mut active {
+                    *since += 1;
+                    if *since == *every {
+                        *since = 0;
+                        // interrupts inside a service routine are ignored
+                        let isr = expansion.expand(ops, *base, limit, &mut Vec::new());
+                        instructions += isr
+                            .iter()
+                            .filter(|event| matches!(event, TraceEvent::Instruction(_)))
+                            .count();
+                        interleaved.extend(isr);
+                    }
+                }
+            }
+        }
+        events = interleaved;
+    }
+
+    // the last op may have overshot the limit
+    let mut instructions = 0;
+    events.retain(|event| match event {
+        TraceEvent::Instruction(_) => {
+            instructions += 1;
+            instructions <= limit
+        }
+        TraceEvent::FlushLine(_)
+        | TraceEvent::Load(_)
+        | TraceEvent::Store(_)
+        | TraceEvent::Client(_) => instructions < limit,
+    });
+    events.into_iter()
+}
+
+/// an interrupt encountered while expanding: (index of the next event, every, service routine, base)
+type PendingInterrupt<'a, 'b> = (usize, usize, &'b [Op<'a>], usize);
+
+/// state shared by the expansion of a compare block and its interrupt service routines
+struct Expansion<'a, 'b, 'c> {
+    block_map: &'b HashMap<&'a str, NamedBlock<'a>>,
+    choices: &'c [usize],
+    rng: StdRng,
+    switch_count: usize,
+}
+
+impl<'a, 'b> Expansion<'a, 'b, '_> {
+    /// expands `ops` with `base` added to all addresses, stops after roughly `limit` instructions
+    fn expand(
+        &mut self,
+        ops: &'b [Op<'a>],
+        base: usize,
+        limit: usize,
+        interrupts: &mut Vec<PendingInterrupt<'a, 'b>>,
+    ) -> Vec<TraceEvent> {
+        let block_map = self.block_map;
+        let choices = self.choices;
+        let rng = &mut self.rng;
+        let mut events = Vec::new();
+        // events that are not instructions
+        let mut others = 0;
+
+        // every op is queued with the offset `tile` adds to its addresses and, for loops,
+        // the number of iterations already done. `None` marks the return from a called block
+        let mut queue = Vec::<Option<(&Op<'a>, usize, usize)>>::from_iter(
+            ops.iter().rev().map(|op| Some((op, base, 0))),
+        );
+        let enqueue = |queue: &mut Vec<Option<(&'b Op<'a>, usize, usize)>>,
+                       ops: &'b [Op<'a>],
+                       base: usize| {
+            queue.extend(ops.iter().rev().map(|op| Some((op, base, 0))));
+        };
+        while let Some(entry) = queue.pop() {
+            if events.len() - others >= limit {
+                break;
+            }
+            let Some((op, base, iterations)) = entry else {
+                continue;
+            };
+
+            match op {
+                Op::Range {
+                    addr_start,
+                    instr_length,
+                    addr_end,
+                } => events.extend((0..(*addr_end - *addr_start) * 8 / *instr_length).map(|i| {
+                    TraceEvent::Instruction(instruction_at(
+                        base + *addr_start,
+                        i * *instr_length,
+                        *instr_length,
+                    ))
+                })),
+                Op::BlockCall { block_name } => {
+                    queue.push(None);
+                    enqueue(&mut queue, &block_map.get(block_name).unwrap().ops, base);
+                }
+                Op::TailCall { block_name } => {
+                    // the rest of the current block is skipped, the callee returns to its caller
+                    while queue.pop_if(|entry| entry.is_some()).is_some() {}
+                    enqueue(&mut queue, &block_map.get(block_name).unwrap().ops, base);
+                }
+                Op::Loop {
+                    count,
+                    block,
+                    break_probability,
+                } => {
+                    // the loop is queued again behind every iteration, so `loop(inf)` stops at the
+                    // limit and the break after an iteration is only drawn once it ran
+                    let done = count.is_some_and(|count| iterations >= count)
+                        || (iterations > 0
+                            && break_probability.is_some_and(|p| p > 0.0 && rng.random_bool(p)));
+                    if !done {
+                        queue.push(Some((op, base, iterations + 1)));
+                        enqueue(&mut queue, &block.ops, base);
+                    }
+                }
+                Op::Tile {
+                    count,
+                    stride,
+                    block,
+                } => {
+                    for i in (0..*count).rev() {
+                        enqueue(&mut queue, &block.ops, base + i * stride);
+                    }
+                }
+                Op::Switch { cases } if !choices.is_empty() => {
+                    let choice = choices.get(self.switch_count % choices.len()).unwrap();
+                    self.switch_count += 1;
+
+                    enqueue(
+                        &mut queue,
+                        &cases.get(choice % cases.len()).unwrap().block.ops,
+                        base,
+                    );
+                }
+                Op::Switch { cases } => {
+                    let mut weights: Vec<(usize, usize)> = cases
+                        .iter()
+                        .enumerate()
+                        .map(|(i, case)| (i, case.weight))
+                        .collect();
+                    weights.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+
+                    let total_weights = weights.iter().map(|(_, weight)| weight).sum();
+                    let random = rng.random_range(0..=total_weights);
+
+                    let mut sum = 0;
+                    for (i, weight) in weights {
+                        sum += weight;
+                        if sum >= random {
+                            enqueue(&mut queue, &cases.get(i).unwrap().block.ops, base);
+                            break;
+                        }
+                    }
+                }
+                Op::Random {
+                    count,
+                    addr_start,
+                    instr_length,
+                    addr_end,
+                } => {
+                    // pick random instruction slots so the addresses stay aligned
+                    let slots = (*addr_end - *addr_start) * 8 / *instr_length;
+                    events.extend((0..*count).map(|_| {
+                        TraceEvent::Instruction(instruction_at(
+                            base + *addr_start,
+                            rng.random_range(0..slots) * *instr_length,
+                            *instr_length,
+                        ))
+                    }));
+                }
+                Op::Zipf {
+                    count,
+                    exponent,
+                    line_size,
+                    addr_start,
+                    instr_length,
+                    addr_end,
+                } => {
+                    // the slot at the start of the range has rank 1 and is the most frequent one
+                    let slots = (*addr_end - *addr_start) / *line_size;
+                    let cumulative_weights: Vec<f64> = (1..=slots)
+                        .scan(0.0, |sum, rank| {
+                            *sum += (rank as f64).powf(-exponent);
+                            Some(*sum)
+                        })
+                        .collect();
+                    let total_weights = *cumulative_weights.last().unwrap();
+
+                    events.extend((0..*count).map(|_| {
+                        let random = rng.random_range(0.0..total_weights);
+                        let slot = cumulative_weights.partition_point(|&sum| sum <= random);
+                        TraceEvent::Instruction(instruction_at(
+                            base + *addr_start,
+                            slot * *line_size * 8,
+                            *instr_length,
+                        ))
+                    }));
+                }
+                Op::FlushLine { address } => {
+                    events.push(TraceEvent::FlushLine(base + address));
+                    others += 1;
+                }
+                Op::Client { client } => {
+                    events.push(TraceEvent::Client(*client));
+                    others += 1;
+                }
+                Op::DataAccess {
+                    address,
+                    store: false,
+                } => {
+                    events.push(TraceEvent::Load(base + address));
+                    others += 1;
+                }
+                Op::DataAccess {
+                    address,
+                    store: true,
+                } => {
+                    events.push(TraceEvent::Store(base + address));
+                    others += 1;
+                }
+                Op::Stride { start, count, step } => {
+                    events.extend((0..*count).map(|i| TraceEvent::Load(base + start + i * step)));
+                    others += count;
+                }
+                Op::Interrupt { every, block } => {
+                    let already_active = interrupts.iter().any(|(_, _, ops, active_base)| {
+                        std::ptr::eq(*ops, block.ops.as_slice()) && *active_base == base
+                    });
+                    if !already_active {
+                        interrupts.push((events.len(), *every, &block.ops, base));
+                    }
+                }
+                Op::InstrLength { .. } => {}
+                Op::Jump { .. } | Op::UnresolvedRange { .. } | Op::UnresolvedRandom { .. } => {
+                    unreachable!("resolved while parsing")
+                }
+            }
+        }
+
+        events
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: usize,
+    pub length: usize,
+}
+
+/// the instruction of `instr_length` bits starting `bit_offset` bits after `address`
+///
+/// instruction sizes that are not a multiple of 8 bits are widened to the bytes they touch
+fn instruction_at(address: usize, bit_offset: usize, instr_length: usize) -> Instruction {
+    let first_bit = bit_offset % 8;
+    Instruction {
+        address: address + bit_offset / 8,
+        length: (first_bit + instr_length).div_ceil(8) * 8,
+    }
+}
+
+/// an element of the expanded stream of a compare block
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraceEvent {
+    Instruction(Instruction),
+    /// invalidates the cache-line containing the address
+    FlushLine(usize),
+    /// data access, only simulated by [`crate::simulation::Simulation::simulate_hierarchy`]
+    Load(usize),
+    /// data access, only simulated by [`crate::simulation::Simulation::simulate_hierarchy`]
+    Store(usize),
+    /// the following accesses are made by this client, see [`crate::lru::LruCache::with_way_mask`]
+    Client(usize),
+}
+
+impl From<Instruction> for TraceEvent {
+    fn from(instruction: Instruction) -> Self {
+        TraceEvent::Instruction(instruction)
+    }
+}
+
+/// renders the instruction as a range containing only this instruction
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "{:#X}..{}..{:#X}",
+            self.address,
+            self.length,
+            self.address + self.length / 8
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct NamedBlock<'a> {
+    compare: bool,
+    name: &'a str,
+    ops: Vec<Op<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Block<'a> {
+    ops: Vec<Op<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Op<'a> {
+    Range {
+        addr_start: usize,
+        instr_length: usize,
+        addr_end: usize,
+    },
+    BlockCall {
+        block_name: &'a str,
+    },
+    /// continues in `block_name` without returning to the rest of the current block
+    TailCall {
+        block_name: &'a str,
+    },
+    /// a count of `None` (`loop(inf)`) repeats forever
+    ///
+    /// with a `break_probability` the loop ends early after each iteration with that probability
+    Loop {
+        count: Option<usize>,
+        block: Block<'a>,
+        break_probability: Option<f64>,
+    },
+    /// repeats `block` `count` times, adding `i * stride` to all addresses of the i-th repetition
+    Tile {
+        count: usize,
+        stride: usize,
+        block: Block<'a>,
+    },
+    Switch {
+        cases: Vec<SwitchCase<'a>>,
+    },
+    /// injects the service routine `block` after every `every` instructions
+    /// until the end of the compare block
+    Interrupt {
+        every: usize,
+        block: Block<'a>,
+    },
+    Random {
+        count: usize,
+        addr_start: usize,
+        instr_length: usize,
+        addr_end: usize,
+    },
+    /// ranks the `line_size` byte slots of the range, the slot at `addr_start` is the most frequent.
+    /// each draw is an instruction at the start of a slot
+    Zipf {
+        count: usize,
+        exponent: f64,
+        line_size: usize,
+        addr_start: usize,
+        instr_length: usize,
+        addr_end: usize,
+    },
+    /// invalidates the cache-line containing `address`
+    FlushLine {
+        address: usize,
+    },
+    /// the client of the following accesses until the next `client`
+    Client {
+        client: usize,
+    },
+    /// a data access to `address`
+    DataAccess {
+        address: usize,
+        store: bool,
+    },
+    /// `count` loads starting at `start`, each `step` bytes after the previous one
+    Stride {
+        start: usize,
+        count: usize,
+        step: usize,
+    },
+    /// sets the instruction size of [`Op::UnresolvedRange`] for the rest of the block
+    InstrLength {
+        instr_length: usize,
+    },
+    /// replaced by a single instruction [`Op::Range`] after parsing
+    Jump {
+        target: usize,
+    },
+    /// replaced by [`Op::Range`] after parsing
+    UnresolvedRange {
+        addr_start: Address,
+        instr_length: Option<usize>,
+        addr_end: Address,
+    },
+    /// `random(N) in addr_start..addr_end`, replaced by [`Op::Random`] after parsing
+    UnresolvedRandom {
+        count: usize,
+        addr_start: usize,
+        addr_end: usize,
+    },
+}
+
+impl Op<'_> {
+    /// slot size of `zipf` without an explicit line size
+    const ZIPF_LINE_SIZE: usize = 64;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Address {
+    Absolute(usize),
+    /// relative to the cursor
+    Forward(usize),
+    /// relative to the cursor
+    Backward(usize),
+}
+
+/// renders the op in trace syntax, nested blocks are indented by 4 spaces
+impl std::fmt::Display for Op<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn indented(block: &Block<'_>) -> String {
+            block
+                .ops
+                .iter()
+                .flat_map(|op| {
+                    op.to_string()
+                        .lines()
+                        .map(|line| format!("    {line}\n"))
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        }
+
+        match self {
+            Op::Range {
+                addr_start,
+                instr_length,
+                addr_end,
+            } => f.write_fmt(format_args!(
+                "{addr_start:#X}..{instr_length}..{addr_end:#X}"
+            )),
+            Op::BlockCall { block_name } => f.write_fmt(format_args!("{block_name}()")),
+            Op::TailCall { block_name } => f.write_fmt(format_args!("tailcall '{block_name}'")),
+            Op::Loop {
+                count,
+                block,
+                break_probability,
+            } => {
+                match count {
+                    None => f.write_str("loop(inf) ")?,
+                    Some(count) => f.write_fmt(format_args!("loop({count}) "))?,
+                }
+                if let Some(break_probability) = break_probability {
+                    f.write_fmt(format_args!("break({break_probability}) "))?;
+                }
+                f.write_fmt(format_args!("{{\n{}}}", indented(block)))
+            }
+            Op::Tile {
+                count,
+                stride,
+                block,
+            } => f.write_fmt(format_args!(
+                "tile({count}, {stride:#X}) {{\n{}}}",
+                indented(block)
+            )),
+            Op::Interrupt { every, block } => f.write_fmt(format_args!(
+                "interrupt(every {every}) {{\n{}}}",
+                indented(block)
+            )),
+            Op::Switch { cases } => {
+                f.write_str("switch:\n")?;
+                for case in cases {
+                    f.write_fmt(format_args!(
+                        "    ({}): {{\n{}    }}\n",
+                        case.weight,
+                        indented(&case.block)
+                            .lines()
+                            .map(|line| format!("    {line}\n"))
+                            .collect::<String>()
+                    ))?;
+                }
+                f.write_str("endswitch")
+            }
+            Op::Random {
+                count,
+                addr_start,
+                instr_length,
+                addr_end,
+            } => f.write_fmt(format_args!(
+                "random({count}) in {addr_start:#X}..{instr_length}..{addr_end:#X}"
+            )),
+            Op::Zipf {
+                count,
+                exponent,
+                line_size,
+                addr_start,
+                instr_length,
+                addr_end,
+            } => f.write_fmt(format_args!(
+                "zipf({count}, {exponent}, {line_size}) in {addr_start:#X}..{instr_length}..{addr_end:#X}"
+            )),
+            Op::InstrLength { instr_length } => {
+                f.write_fmt(format_args!("instr_length {instr_length}"))
+            }
+            Op::Jump { target } => f.write_fmt(format_args!("jump {target:#X}")),
+            Op::FlushLine { address } => f.write_fmt(format_args!("flush {address:#X}")),
+            Op::Client { client } => f.write_fmt(format_args!("client {client}")),
+            Op::DataAccess {
+                address,
+                store: false,
+            } => f.write_fmt(format_args!("load {address:#X}")),
+            Op::DataAccess {
+                address,
+                store: true,
+            } => f.write_fmt(format_args!("store {address:#X}")),
+            Op::Stride { start, count, step } => {
+                f.write_fmt(format_args!("stride({start:#X}, {count}, {step:#X})"))
+            }
+            Op::UnresolvedRange {
+                addr_start,
+                instr_length: Some(instr_length),
+                addr_end,
+            } => f.write_fmt(format_args!("{addr_start}..{instr_length}..{addr_end}")),
+            Op::UnresolvedRange {
+                addr_start,
+                instr_length: None,
+                addr_end,
+            } => f.write_fmt(format_args!("{addr_start}..{addr_end}")),
+            Op::UnresolvedRandom {
+                count,
+                addr_start,
+                addr_end,
+            } => f.write_fmt(format_args!(
+                "random({count}) in {addr_start:#X}..{addr_end:#X}"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Address::Absolute(address) => f.write_fmt(format_args!("{address:#X}")),
+            Address::Forward(offset) => f.write_fmt(format_args!("+{offset:#X}")),
+            Address::Backward(offset) => f.write_fmt(format_args!("-{offset:#X}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SwitchCase<'a> {
+    weight: usize,
+    block: Block<'a>,
+}
+
+enum TopLevel<'a> {
+    Block(NamedBlock<'a>),
+    Preamble(&'a str),
+}
+
+fn preamble<'a>(input: &mut Input<'a>) -> ModalResult<&'a str> {
+    preceded(
+        ("preamble", space1),
+        cut_err(terminated(delimited('\'', block_name, '\''), end))
+            .context(StrContext::Label("preamble block name")),
+    )
+    .parse_next(input)
+}
+
+fn block_name<'a>(input: &mut Input<'a>) -> ModalResult<&'a str> {
+    take_while(1.., (AsChar::is_alphanum, '_', '-')).parse_next(input)
+}
+
+fn named_block<'a>(input: &mut Input<'a>) -> ModalResult<NamedBlock<'a>> {
+    (
+        opt(terminated("compare", space1)).map(|cmp| cmp.is_some()),
+        delimited('\'', cut_err(block_name), cut_err('\'')),
+        cut_err(block),
+    )
+        .map(|(compare, name, Block { ops })| NamedBlock { compare, name, ops })
+        .parse_next(input)
+}
+
+fn block<'a>(input: &mut Input<'a>) -> ModalResult<Block<'a>> {
+    let nesting = input.state;
+    // the body of a named block is the outermost block and does not count towards the depth
+    if nesting.open > nesting.limit {
+        return cut_err(fail).context(NESTED_TOO_DEEP).parse_next(input);
+    }
+
+    input.state.open += 1;
+    let block = delimited(
+        (multispace, '{').context(StrContext::Label("block start")),
+        cut_err(
+            repeat_till(
+                0..,
+                op,
+                (multispace, '}').context(StrContext::Label("block end")),
+            )
+            .map(|(ops, _)| Block { ops }),
+        ),
+        end,
+    )
+    .parse_next(input);
+    input.state = nesting;
+    block
+}
+
+fn op<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    // important: try 'relative_range' before 'range' before 'default_range' because of ambiguity
+    preceded(
+        multispace,
+        alt((
+            block_call,
+            tail_call,
+            instr_length,
+            jump,
+            flush_line,
+            data_access,
+            stride,
+            client,
+            ifetch,
+            looop,
+            tile,
+            interrupt,
+            switch,
+            random,
+            zipf,
+            relative_range,
+            range,
+            default_range,
+        )),
+    )
+    .context(StrContext::Label("statement"))
+    .parse_next(input)
+}
+
+fn range<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    terminated(range_bounds, end)
+        .map(|(addr_start, instr_length, addr_end)| Op::Range {
+            addr_start,
+            instr_length,
+            addr_end,
+        })
+        .parse_next(input)
+}
+
+fn random<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    preceded(
+        "random",
+        cut_err(terminated(
+            (
+                delimited(
+                    (space, '(', multispace),
+                    decimal_integer,
+                    (multispace, ')', space),
+                )
+                .context(StrContext::Label("random count")),
+                preceded(
+                    ("in", space),
+                    alt((
+                        range_bounds.map(|(addr_start, instr_length, addr_end)| {
+                            (addr_start, Some(instr_length), addr_end)
+                        }),
+                        separated_pair(integer, "..", integer)
+                            .verify(|(addr_start, addr_end)| addr_start < addr_end)
+                            .context(StrContext::Label("range: range is empty"))
+                            .map(|(addr_start, addr_end)| (addr_start, None, addr_end)),
+                    )),
+                ),
+            ),
+            end,
+        ))
+        .map(
+            |(count, (addr_start, instr_length, addr_end))| match instr_length {
+                Some(instr_length) => Op::Random {
+                    count,
+                    addr_start,
+                    instr_length,
+                    addr_end,
+                },
+                None => Op::UnresolvedRandom {
+                    count,
+                    addr_start,
+                    addr_end,
+                },
+            },
+        ),
+    )
+    .parse_next(input)
+}
+
+fn zipf<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    preceded(
+        "zipf",
+        cut_err(terminated(
+            (
+                delimited(
+                    (space, '(', space),
+                    (
+                        decimal_integer,
+                        preceded(
+                            (space, ',', space),
+                            float
+                                .verify(|exponent: &f64| exponent.is_finite() && *exponent >= 0.0)
+                                .context(StrContext::Label("zipf exponent")),
+                        ),
+                        opt(preceded((space, ',', space), decimal_integer)),
+                    ),
+                    (space, ')', space),
+                )
+                .context(StrContext::Label("zipf count, exponent and line size")),
+                preceded(("in", space), range_bounds),
+            ),
+            end,
+        ))
+        .map(
+            |((count, exponent, line_size), (addr_start, instr_length, addr_end))| Op::Zipf {
+                count,
+                exponent,
+                line_size: line_size.unwrap_or(Op::ZIPF_LINE_SIZE),
+                addr_start,
+                instr_length,
+                addr_end,
+            },
+        ),
+    )
+    .parse_next(input)
+}
+
+/// parses and validates `addr_start..instr_length..addr_end`
+fn range_bounds(input: &mut Input<'_>) -> ModalResult<(usize, usize, usize)> {
+    fn range_inner(input: &mut Input<'_>) -> ModalResult<(usize, usize, usize)> {
+        (integer, delimited("..", integer, ".."), integer).parse_next(input)
+    }
+
+    let (addr_start, instr_length, addr_end) = peek(range_inner).parse_next(input)?;
+
+    // cut because a three part range can't be mistaken for a range using the default instruction size
+    if addr_start >= addr_end {
+        return cut_err(fail)
+            .context(StrContext::Label("range: range is empty"))
+            .parse_next(input)?;
+    }
+
+    if instr_length == 0 {
+        return cut_err(fail)
+            .context(StrContext::Label("range: instruction size is zero"))
+            .parse_next(input)?;
+    }
+
+    if (addr_end - addr_start) * 8 % instr_length != 0 {
+        return cut_err(fail)
+            .context(StrContext::Label(
+                "range: instruction size does not cleanly fit in range",
+            ))
+            .parse_next(input)?;
+    }
+
+    range_inner.parse_next(input)
+}
+
+/// `addr_start..addr_end` using the instruction size set by `instr_length`
+fn default_range<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    let (addr_start, addr_end) = peek(separated_pair(integer, "..", integer)).parse_next(input)?;
+
+    if addr_start >= addr_end {
+        return cut_err(fail)
+            .context(StrContext::Label("range: range is empty"))
+            .parse_next(input)?;
+    }
+
+    terminated(separated_pair(integer, "..", integer), end)
+        .map(|(addr_start, addr_end)| Op::UnresolvedRange {
+            addr_start: Address::Absolute(addr_start),
+            instr_length: None,
+            addr_end: Address::Absolute(addr_end),
+        })
+        .parse_next(input)
+}
+
+/// a range where at least one address is relative to the cursor, e.g. `-0x40..32..+0x0`
+fn relative_range<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    fn address(input: &mut Input<'_>) -> ModalResult<Address> {
+        alt((
+            preceded('+', cut_err(integer)).map(Address::Forward),
+            preceded('-', cut_err(integer)).map(Address::Backward),
+            integer.map(Address::Absolute),
+        ))
+        .parse_next(input)
+    }
+
+    terminated(
+        (
+            address,
+            "..",
+            opt(preceded(
+                peek(take_while(1, AsChar::is_dec_digit)),
+                terminated(integer, ".."),
+            )),
+            address,
+        )
+            .verify(|(addr_start, _, _, addr_end)| {
+                !matches!(
+                    (addr_start, addr_end),
+                    (Address::Absolute(_), Address::Absolute(_))
+                )
+            }),
+        end,
+    )
+    .map(
+        |(addr_start, _, instr_length, addr_end)| Op::UnresolvedRange {
+            addr_start,
+            instr_length,
+            addr_end,
+        },
+    )
+    .parse_next(input)
+}
+
+fn instr_length<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    preceded(
+        ("instr_length", space1),
+        cut_err(terminated(
+            decimal_integer
+                .verify(|instr_length| *instr_length > 0)
+                .context(StrContext::Label("instr_length: instruction size is zero")),
+            end,
+        ))
+        .map(|instr_length| Op::InstrLength { instr_length }),
+    )
+    .parse_next(input)
+}
+
+fn tail_call<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    preceded(
+        ("tailcall", space1),
+        cut_err(
+            terminated(delimited('\'', block_name, '\''), end)
+                .context(StrContext::Label("tail call block name")),
+        ),
+    )
+    .map(|block_name| Op::TailCall { block_name })
+    .parse_next(input)
+}
+
+fn jump<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    preceded(
+        ("jump", space1),
+        cut_err(terminated(integer, end).context(StrContext::Label("jump target"))),
+    )
+    .map(|target| Op::Jump { target })
+    .parse_next(input)
+}
+
+fn flush_line<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    preceded(
+        ("flush", space1),
+        cut_err(terminated(integer, end).context(StrContext::Label("flush address"))),
+    )
+    .map(|address| Op::FlushLine { address })
+    .parse_next(input)
+}
+
+fn data_access<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    (
+        terminated(alt(("load".value(false), "store".value(true))), space1),
+        cut_err(terminated(integer, end).context(StrContext::Label("data address"))),
+    )
+        .map(|(store, address)| Op::DataAccess { address, store })
+        .parse_next(input)
+}
+
+fn stride<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    preceded(
+        "stride",
+        cut_err(terminated(
+            delimited(
+                (space, '(', multispace),
+                (
+                    integer,
+                    preceded((multispace, ',', multispace), decimal_integer),
+                    preceded((multispace, ',', multispace), integer),
+                ),
+                (multispace, ')'),
+            )
+            .verify(|(start, count, step): &(usize, usize, usize)| {
+                count
+                    .saturating_sub(1)
+                    .checked_mul(*step)
+                    .and_then(|offset| start.checked_add(offset))
+                    .is_some()
+            })
+            .context(StrContext::Label("stride start, count and step")),
+            end,
+        )),
+    )
+    .map(|(start, count, step)| Op::Stride { start, count, step })
+    .parse_next(input)
+}
+
+fn client<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    preceded(
+        ("client", space1),
+        cut_err(terminated(decimal_integer, end).context(StrContext::Label("client id"))),
+    )
+    .map(|client| Op::Client { client })
+    .parse_next(input)
+}
+
+/// an explicitly tagged instruction range, ranges without a tag are instruction fetches as well
+fn ifetch<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    preceded(
+        ("ifetch", space1),
+        cut_err(alt((relative_range, range, default_range))),
+    )
+    .parse_next(input)
+}
+
+fn block_call<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    terminated(block_name, ("()", end))
+        .map(|function_name| Op::BlockCall {
+            block_name: function_name,
+        })
+        .parse_next(input)
+        .map_err(|e| e.backtrack()) // remove cut_err from function_name
+}
+
+fn looop<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    preceded(
+        "loop",
+        cut_err((
+            delimited(
+                (space, '(', multispace),
+                alt((
+                    "inf".value(None),
+                    decimal_integer.map(Some),
+                    // only numbers that do not fit into a usize are left
+                    preceded(
+                        peek(take_while(1.., '0'..='9')),
+                        cut_err(fail).context(LOOP_COUNT_TOO_LARGE),
+                    ),
+                )),
+                (multispace, ')', space),
+            )
+            .context(StrContext::Label("loop count")),
+            opt(delimited(
+                ("break", space, '(', space),
+                float
+                    .verify(|p: &f64| (0.0..=1.0).contains(p))
+                    .context(StrContext::Label("break probability")),
+                (space, ')', space),
+            )),
+            block,
+        ))
+        .map(|(count, break_probability, block)| Op::Loop {
+            count,
+            block,
+            break_probability,
+        }),
+    )
+    .parse_next(input)
+}
+
+fn tile<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    preceded(
+        "tile",
+        cut_err((
+            delimited(
+                (space, '(', multispace),
+                separated_pair(decimal_integer, (multispace, ',', multispace), integer),
+                (multispace, ')', space),
+            )
+            .context(StrContext::Label("tile count and stride")),
+            block,
+        ))
+        .map(|((count, stride), block)| Op::Tile {
+            count,
+            stride,
+            block,
+        }),
+    )
+    .parse_next(input)
+}
+
+fn interrupt<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    preceded(
+        "interrupt",
+        cut_err((
+            delimited(
+                (space, '(', multispace, "every", space1),
+                decimal_integer,
+                (multispace, ')', space),
+            )
+            .context(StrContext::Label("interrupt interval")),
+            block,
+        ))
+        .map(|(every, block)| Op::Interrupt { every, block }),
+    )
+    .parse_next(input)
+}
+
+fn switch<'a>(input: &mut Input<'a>) -> ModalResult<Op<'a>> {
+    preceded(
+        ("switch:", cut_err(end)),
+        cut_err(terminated(
+            repeat_till(
+                1..,
+                switch_case,
+                (multispace, "endswitch").context(StrContext::Label("switch end")),
+            )
+            .map(|(cases, _)| Op::Switch { cases }),
+            end,
+        )),
+    )
+    .parse_next(input)
+}
+
+fn switch_case<'a>(input: &mut Input<'a>) -> ModalResult<SwitchCase<'a>> {
+    separated_pair(
+        delimited(
+            (space, '(', multispace),
+            decimal_integer,
+            (multispace, ')', space),
+        ),
+        (space, ':', space),
+        block,
+    )
+    .map(|(weight, block)| SwitchCase { weight, block })
+    .context(StrContext::Label("switch case"))
+    .parse_next(input)
+}
+
+fn end(input: &mut Input<'_>) -> ModalResult<()> {
+    (space, alt((line_ending, eof)), multispace)
+        .void()
+        .context(StrContext::Label("newline"))
+        .parse_next(input)
+}
+
+fn space(input: &mut Input<'_>) -> ModalResult<()> {
+    (space0, opt(("//", till_line_ending)), space0)
+        .void()
+        .context(StrContext::Label("newline"))
+        .parse_next(input)
+}
+
+/// runs `parser` on a plain `&str` with a [`Nesting::limit`] of `limit`
+fn with_nesting_limit<'a, O>(
+    limit: usize,
+    mut parser: impl Parser<Input<'a>, O, ErrMode<ContextError>>,
+) -> impl Parser<&'a str, O, ErrMode<ContextError>> {
+    move |input: &mut &'a str| {
+        let mut stateful = Input {
+            input: *input,
+            state: Nesting { open: 0, limit },
+        };
+        let output = parser.parse_next(&mut stateful);
+        *input = stateful.input;
+        output
+    }
+}
+
+fn integer(input: &mut Input<'_>) -> ModalResult<usize> {
+    parse::integer.parse_next(&mut input.input)
+}
+
+fn decimal_integer(input: &mut Input<'_>) -> ModalResult<usize> {
+    parse::decimal_integer.parse_next(&mut input.input)
+}
+
+fn multispace(input: &mut Input<'_>) -> ModalResult<()> {
+    (
+        multispace0,
+        repeat::<_, _, (), _, _>(0.., ("//", till_line_ending, multispace0)),
+        multispace0,
+    )
+        .void()
+        .context(StrContext::Label("newline"))
+        .parse_next(input)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        DiffEntry, Instruction, Op, TraceEvent, TraceFile, TraceParseError, diff, generate_large,
+        generate_random,
+    };
+
+    fn expand(input: &str) -> Vec<(&str, Vec<Instruction>)> {
+        TraceFile::try_from(input)
+            .unwrap()
+            .into_iter()
+            .map(|(name, block)| (name, block.collect()))
+            .collect()
+    }
 
     #[test]
     fn check_all_traces() {
         for file in std::fs::read_dir("./traces/").unwrap() {
             let file = file.unwrap();
 
-            if file.metadata().unwrap().is_file() {
+            if file.metadata().unwrap().is_file()
+                && file.path().extension().is_some_and(|ext| ext == "trace")
+            {
                 let file_content = std::fs::read_to_string(file.path()).unwrap();
                 let trace = TraceFile::try_from(file_content.as_str());
                 assert!(
@@ -400,4 +2529,679 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn random_addresses_in_range() {
+        let traces = expand("compare 'a' {\n    random(100) in 0x100..32..0x200\n}\n");
+        let (_, instructions) = traces.first().unwrap();
+
+        assert_eq!(instructions.len(), 100);
+        for instruction in instructions {
+            assert!((0x100..0x200).contains(&instruction.address));
+            assert_eq!(instruction.address % 4, 0);
+            assert_eq!(instruction.length, 32);
+        }
+
+        // without an instruction size the one set by `instr_length` is used
+        let traces =
+            expand("compare 'a' {\n    instr_length 16\n    random(10) in 0x0..0x1000\n}\n");
+        let (_, instructions) = traces.first().unwrap();
+        assert_eq!(instructions.len(), 10);
+        assert!(
+            instructions
+                .iter()
+                .all(|i| i.address < 0x1000 && i.address % 2 == 0 && i.length == 16)
+        );
+        assert!(TraceFile::try_from("compare 'a' {\n    random(10) in 0x0..0x1000\n}\n").is_err());
+    }
+
+    #[test]
+    fn zipf_skews_towards_first_slot() {
+        let traces = expand("compare 'a' {\n    zipf(10000, 1.0) in 0x0..32..0x1000\n}\n");
+        let (_, instructions) = traces.first().unwrap();
+
+        assert_eq!(instructions.len(), 10_000);
+        assert!(
+            instructions
+                .iter()
+                .all(|i| i.address < 0x1000 && i.address % 64 == 0)
+        );
+
+        // 64 slots of 64 bytes with s = 1: p(rank 1) = 1 / H(64) ~ 0.211
+        let hottest = instructions.iter().filter(|i| i.address == 0x0).count();
+        let fraction = hottest as f64 / instructions.len() as f64;
+        assert!((0.18..0.24).contains(&fraction), "fraction = {fraction}");
+
+        let coldest = instructions.iter().filter(|i| i.address == 0xFC0).count();
+        assert!(coldest < hottest / 16);
+
+        // 4 slots of 16 bytes
+        let traces = expand("compare 'a' {\n    zipf(100, 1.0, 16) in 0x0..32..0x40\n}\n");
+        assert!(traces[0].1.iter().all(|i| i.address % 16 == 0));
+        assert!(
+            TraceFile::try_from("compare 'a' {\n    zipf(10, 1.0) in 0x20..32..0x60\n}\n").is_err()
+        );
+    }
+
+    #[test]
+    fn trailing_input_after_last_block() {
+        let error = TraceFile::try_from("'a' {\n    0x0..8..0x8\n}\ngarbage\n")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("line 4"), "{error}");
+        assert!(error.contains("garbage"), "{error}");
+        assert!(error.contains("invalid input after last block"), "{error}");
+        assert!(error.contains("expected block definition"), "{error}");
+
+        assert!(TraceFile::try_from("'a' { 0x0..8..0x8 } garbage").is_err());
+    }
+
+    #[test]
+    fn static_stats_of_nested_loops() {
+        let trace = TraceFile::try_from(
+            "'f' {\n    0x200..32..0x210\n}\n\ncompare 'a' {\n    loop(3) {\n        0x0..32..0x10\n        loop(2) {\n            0x8..32..0x18\n            f()\n        }\n    }\n    switch:\n        (1): {\n            0x1000..32..0x1004\n        }\n        (1): {\n            0x1004..32..0x1010\n        }\n    endswitch\n}\n",
+        )
+        .unwrap();
+        let stats = trace.static_stats();
+        let [(name, stats)] = stats.as_slice() else {
+            panic!("expected exactly one compare block");
+        };
+
+        assert_eq!(*name, "a");
+        // 3 * (4 + 2 * (4 + 4)) + max(1, 3)
+        assert_eq!(stats.instruction_count, 63);
+        assert_eq!(
+            stats.address_ranges,
+            vec![0x0..0x18, 0x200..0x210, 0x1000..0x1010]
+        );
+    }
+
+    #[test]
+    fn switch_choices_override_rng() {
+        let input = "compare 'a' {\n    loop(2) {\n        switch:\n            (1): {\n                0x0..32..0x4\n            }\n            (1): {\n                0x100..32..0x104\n            }\n        endswitch\n    }\n}\n";
+
+        let addresses = |choices: &[usize]| -> Vec<usize> {
+            let (_, block) = TraceFile::try_from(input)
+                .unwrap()
+                .into_iter_with_choices(choices)
+                .next()
+                .unwrap();
+            block.map(|instruction| instruction.address).collect()
+        };
+
+        assert_eq!(addresses(&[1, 0]), vec![0x100, 0x0]);
+        assert_eq!(addresses(&[0, 1]), vec![0x0, 0x100]);
+        assert_eq!(addresses(&[1]), vec![0x100, 0x100]);
+    }
+
+    #[test]
+    fn default_instruction_length() {
+        let traces = expand(
+            "compare 'a' {\n    instr_length 16\n    0x0..0x4\n    0x10..32..0x18\n    loop(1) {\n        0x20..0x22\n    }\n    instr_length 32\n    0x30..0x38\n}\n",
+        );
+        let (_, instructions) = traces.first().unwrap();
+        let instructions: Vec<(usize, usize)> = instructions
+            .iter()
+            .map(|instruction| (instruction.address, instruction.length))
+            .collect();
+
+        assert_eq!(
+            instructions,
+            vec![
+                (0x0, 16),
+                (0x2, 16),
+                (0x10, 32),
+                (0x14, 32),
+                (0x20, 16),
+                (0x30, 32),
+                (0x34, 32)
+            ]
+        );
+
+        assert!(TraceFile::try_from("'a' {\n    0x0..0x4\n}\n").is_err());
+        assert!(TraceFile::try_from("'a' {\n    instr_length 32\n    0x0..0x6\n}\n").is_err());
+    }
+
+    #[test]
+    fn relative_ranges_move_cursor() {
+        let traces = expand(
+            "compare 'a' {\n    0x100..32..0x108\n    -0x8..32..+0x0\n    +0x8..16..+0xC\n}\n",
+        );
+        let (_, instructions) = traces.first().unwrap();
+        let addresses: Vec<usize> = instructions
+            .iter()
+            .map(|instruction| instruction.address)
+            .collect();
+
+        assert_eq!(addresses, vec![0x100, 0x104, 0x100, 0x104, 0x110, 0x112]);
+    }
+
+    #[test]
+    fn relative_range_underflow() {
+        assert!(TraceFile::try_from("'a' {\n    0x0..32..0x8\n    -0x10..32..+0x0\n}\n").is_err());
+        assert!(TraceFile::try_from("'a' {\n    -0x4..32..+0x0\n}\n").is_err());
+    }
+
+    #[test]
+    fn jump_continues_at_target() {
+        let traces = expand(
+            "compare 'a' {\n    instr_length 32\n    0x0..0x8\n    jump 0x100\n    +0x0..+0x8\n}\n",
+        );
+        let (_, instructions) = traces.first().unwrap();
+        let addresses: Vec<usize> = instructions
+            .iter()
+            .map(|instruction| instruction.address)
+            .collect();
+
+        assert_eq!(addresses, vec![0x0, 0x4, 0x8, 0x100, 0x104]);
+        assert!(TraceFile::try_from("'a' {\n    jump 0x100\n}\n").is_err());
+    }
+
+    #[test]
+    fn comments_inside_parentheses() {
+        let traces = expand(
+            "compare 'a' {\n    loop( // n times\n        2 // twice\n    ) {\n        0x0..32..0x4\n    }\n    switch:\n        ( // weight\n            1\n        ): {\n            0x10..32..0x14\n        }\n    endswitch\n}\n",
+        );
+        let (_, instructions) = traces.first().unwrap();
+        let addresses: Vec<usize> = instructions
+            .iter()
+            .map(|instruction| instruction.address)
+            .collect();
+
+        assert_eq!(addresses, vec![0x0, 0x0, 0x10]);
+    }
+
+    #[test]
+    fn generated_traces_parse() {
+        for seed in 0..1000 {
+            let trace = generate_random(seed, 3);
+            let trace_file = TraceFile::try_from(trace.as_str())
+                .unwrap_or_else(|e| panic!("seed {seed} failed to parse: {e}\n{trace}"));
+            assert!(trace_file.into_iter().count() > 0);
+        }
+    }
+
+    #[test]
+    fn tile_offsets_repetitions() {
+        let traces = expand(
+            "compare 'a' {\n    tile(4, 0x100) {\n        0x0..8..0x8\n    }\n    +0x0..32..+0x4\n}\n",
+        );
+        let (_, instructions) = traces.first().unwrap();
+        let tile_starts: Vec<usize> = instructions
+            .iter()
+            .step_by(8)
+            .map(|instruction| instruction.address)
+            .collect();
+
+        assert_eq!(instructions.len(), 4 * 8 + 1);
+        assert_eq!(tile_starts, vec![0x0, 0x100, 0x200, 0x300, 0x308]);
+        assert_eq!(instructions[7].address, 0x7);
+
+        // the offsets of the last repetition must not move addresses past usize::MAX
+        let max = usize::MAX;
+        for trace in [
+            format!(
+                "compare 'a' {{\n    tile(3, {:#X}) {{\n        0x0..32..0x4\n    }}\n}}\n",
+                max / 2
+            ),
+            format!(
+                "compare 'a' {{\n    tile(2, 0x10) {{\n        {:#X}..32..{:#X}\n    }}\n}}\n",
+                max - 0xF,
+                max - 0xB
+            ),
+            format!(
+                "'b' {{\n    flush {max:#X}\n}}\n\ncompare 'a' {{\n    tile(2, 0x1) {{\n        b()\n    }}\n}}\n"
+            ),
+        ] {
+            assert!(
+                matches!(
+                    TraceFile::try_from(trace.as_str()),
+                    Err(TraceParseError::SyntaxError(message)) if message.contains("tile(")
+                ),
+                "{trace}"
+            );
+        }
+        assert!(
+            TraceFile::try_from(
+                format!(
+                    "compare 'a' {{\n    tile(2, 0x10) {{\n        {:#X}..32..{:#X}\n    }}\n}}\n",
+                    max - 0x1F,
+                    max - 0x1B
+                )
+                .as_str()
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn diff_reports_first_divergence() {
+        let file_a = "compare 'a' {\n    0x0..32..0x10\n}\n\ncompare 'b' {\n    0x0..32..0x8\n}\n";
+        let file_b = "compare 'a' {\n    0x0..32..0x8\n    0x20..32..0x28\n}\n\ncompare 'b' {\n    0x0..32..0x8\n}\n";
+
+        assert_eq!(diff(file_a, file_a).unwrap(), vec![]);
+        assert_eq!(
+            diff(file_a, file_b).unwrap(),
+            vec![DiffEntry {
+                name: String::from("a"),
+                index: 2,
+                address_a: Some(0x8),
+                address_b: Some(0x20),
+            }]
+        );
+    }
+
+    #[test]
+    fn blocks_in_definition_order() {
+        let input = "compare 'z' {\n    0x0..32..0x4\n}\n\n'helper' {\n    0x0..32..0x4\n}\n\ncompare 'a' {\n    helper()\n}\n\ncompare 'm' {\n    0x0..32..0x4\n}\n";
+
+        for _ in 0..10 {
+            let names: Vec<&str> = expand(input).into_iter().map(|(name, _)| name).collect();
+            assert_eq!(names, vec!["z", "a", "m"]);
+
+            let trace_file = TraceFile::try_from(input).unwrap();
+            let names: Vec<&str> = trace_file
+                .static_stats()
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect();
+            assert_eq!(names, vec!["z", "a", "m"]);
+        }
+    }
+
+    #[test]
+    fn display_in_trace_syntax() {
+        let input = "compare 'a' {\n    loop(10) {\n        0x0..32..0x100\n        switch:\n            (1): {\n                b()\n            }\n        endswitch\n    }\n}\n\n'b' {\n    0x0..32..0x4\n}\n";
+        let trace_file = TraceFile::try_from(input).unwrap();
+        let ops = &trace_file.named_blocks.get("a").unwrap().ops;
+
+        assert_eq!(
+            ops[0].to_string(),
+            "loop(10) {\n    0x0..32..0x100\n    switch:\n        (1): {\n            b()\n        }\n    endswitch\n}"
+        );
+        let Op::Loop { block, .. } = &ops[0] else {
+            panic!("expected a loop");
+        };
+        assert_eq!(block.ops[0].to_string(), "0x0..32..0x100");
+
+        let instruction = Instruction {
+            address: 0x3E,
+            length: 32,
+        };
+        assert_eq!(instruction.to_string(), "0x3E..32..0x42");
+    }
+
+    #[test]
+    fn metadata_header() {
+        let input = "// @meta name=boot clock=1600\n// a regular comment\n// @meta core=cortex-m7\n\ncompare 'a' {\n    // @meta ignored=here\n    0x0..32..0x4\n}\n";
+        let trace_file = TraceFile::try_from(input).unwrap();
+        let metadata = trace_file.metadata();
+
+        assert_eq!(metadata.len(), 3);
+        assert_eq!(metadata.get("name").map(String::as_str), Some("boot"));
+        assert_eq!(metadata.get("clock").map(String::as_str), Some("1600"));
+        assert_eq!(metadata.get("core").map(String::as_str), Some("cortex-m7"));
+
+        assert!(TraceFile::try_from("// @meta name\n'a' {\n    0x0..32..0x4\n}\n").is_err());
+
+        // other directives starting with `@meta` are regular comments
+        let trace_file =
+            TraceFile::try_from("// @metadata name\n// @meta\n'a' {\n    0x0..32..0x4\n}\n")
+                .unwrap();
+        assert!(trace_file.metadata().is_empty());
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let input = "// @meta name=crlf\n// comment\ncompare 'a' {\n    loop(2) { // twice\n        0x0..32..0x8\n    }\n    switch:\n        (1): {\n            0x10..32..0x14\n        }\n    endswitch\n}\n";
+        let crlf = input.replace('\n', "\r\n");
+
+        assert_eq!(expand(&crlf), expand(input));
+        let trace_file = TraceFile::try_from(crlf.as_str()).unwrap();
+        assert_eq!(
+            trace_file.metadata().get("name").map(String::as_str),
+            Some("crlf")
+        );
+
+        for entry in std::fs::read_dir("traces").unwrap() {
+            let path = entry.unwrap().path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "trace")
+            {
+                let content = std::fs::read_to_string(&path)
+                    .unwrap()
+                    .replace("\r\n", "\n")
+                    .replace('\n', "\r\n");
+                assert!(TraceFile::try_from(content.as_str()).is_ok(), "{path:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn interrupt_injected_periodically() {
+        let traces = expand(
+            "compare 'a' {\n    interrupt(every 3) {\n        0x1000..32..0x1008\n    }\n    0x0..32..0x20\n}\n",
+        );
+        let (_, instructions) = traces.first().unwrap();
+        let addresses: Vec<usize> = instructions
+            .iter()
+            .map(|instruction| instruction.address)
+            .collect();
+
+        assert_eq!(
+            addresses,
+            vec![
+                0x0, 0x4, 0x8, 0x1000, 0x1004, 0xC, 0x10, 0x14, 0x1000, 0x1004, 0x18, 0x1C
+            ]
+        );
+        assert!(
+            TraceFile::try_from(
+                "compare 'a' {\n    interrupt(every 0) {\n        0x0..32..0x4\n    }\n}\n"
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn perf_script_import() {
+        let perf_script = "            4005d0\n            4005d4 main+0x4 (/usr/bin/app)\n\n  4005d8 main+0x8 (/usr/bin/app)\n      7f3c10 memcpy+0x0 (/usr/lib/libc.so.6)\n            4005dc\n";
+        let trace_file = TraceFile::from_perf_script(perf_script, 32).unwrap();
+        let addresses: Vec<(&str, Vec<usize>)> = trace_file
+            .into_iter()
+            .map(|(name, block)| (name, block.map(|instruction| instruction.address).collect()))
+            .collect();
+
+        assert_eq!(
+            addresses,
+            vec![(
+                "perf",
+                vec![0x4005D0, 0x4005D4, 0x4005D8, 0x7F3C10, 0x4005DC]
+            )]
+        );
+        assert!(TraceFile::from_perf_script("main+0x4\n", 32).is_err());
+        assert!(TraceFile::from_perf_script("4005d0\n", 12).is_err());
+
+        let near_end = format!("{:x}\n{:x}\n", usize::MAX - 7, usize::MAX - 3);
+        assert!(TraceFile::from_perf_script(&near_end[..near_end.find('\n').unwrap()], 32).is_ok());
+        assert!(matches!(
+            TraceFile::from_perf_script(&near_end, 32),
+            Err(TraceParseError::SyntaxError(message)) if message.starts_with("line 2:")
+        ));
+    }
+
+    #[test]
+    fn oversized_loop_counts() {
+        let error = TraceFile::try_from(
+            "compare 'a' {\n    loop(99999999999999999999) {\n        0x0..32..0x4\n    }\n}\n",
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            TraceParseError::SyntaxError(message)
+                if message == "loop count 99999999999999999999 is too large"
+        ));
+
+        let trace_file = TraceFile::try_from(
+            "compare 'a' {\n    loop(1000000) {\n        loop(2) {\n            0x0..32..0x8\n        }\n    }\n    loop(inf) {\n        0x0..32..0x4\n    }\n}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            trace_file.loop_warnings(1_000_000),
+            vec![String::from(
+                "loop(1000000) in 'a' expands to 4000000 instructions, more than 1000000"
+            )]
+        );
+        assert_eq!(trace_file.loop_warnings(4_000_000), Vec::<String>::new());
+
+        // the largest count is a regular loop
+        let trace_file = TraceFile::try_from(
+            "compare 'a' {\n    loop(18446744073709551615) {\n        0x0..32..0x4\n    }\n}\n",
+        )
+        .unwrap();
+        assert!(!trace_file.static_stats()[0].1.unbounded);
+
+        for body in [
+            "",
+            "flush 0x0\n        load 0x0\n",
+            "loop(0) {\n            0x0..32..0x4\n        }\n",
+        ] {
+            let trace = format!("compare 'a' {{\n    loop(inf) {{\n        {body}}}\n}}\n");
+            let error = TraceFile::try_from(trace.as_str()).unwrap_err().to_string();
+            assert!(error.contains("without instructions"), "{error}");
+        }
+    }
+
+    #[test]
+    fn bit_granular_instruction_size() {
+        let traces =
+            expand("compare 'a' {\n    0x0..12..0x3\n    instr_length 12\n    +0x0..+0x3\n}\n");
+        let (_, instructions) = traces.first().unwrap();
+
+        // the second instruction occupies the bits 12..24 and touches the bytes 1 and 2
+        assert_eq!(
+            instructions,
+            &vec![
+                Instruction {
+                    address: 0x0,
+                    length: 16
+                },
+                Instruction {
+                    address: 0x1,
+                    length: 16
+                },
+                Instruction {
+                    address: 0x3,
+                    length: 16
+                },
+                Instruction {
+                    address: 0x4,
+                    length: 16
+                },
+            ]
+        );
+        assert!(TraceFile::try_from("compare 'a' {\n    0x0..12..0x2\n}\n").is_err());
+    }
+
+    #[test]
+    fn tail_call_does_not_return() {
+        let input = "'b' {\n    0x100..32..0x104\n}\n\n'a' {\n    0x0..32..0x4\n    tailcall 'b'\n    0x10..32..0x14\n}\n\ncompare 'c' {\n    a()\n    0x20..32..0x24\n}\n\ncompare 'd' {\n    loop(3) {\n        0x0..32..0x4\n        tailcall 'b'\n    }\n    0x20..32..0x24\n}\n";
+        let trace_file = TraceFile::try_from(input).unwrap();
+        assert_eq!(
+            trace_file.unreachable_warnings(),
+            ["1 op(s) after 'tailcall 'b'' in 'a' are unreachable and were dropped"]
+        );
+
+        let addresses: Vec<Vec<usize>> = expand(input)
+            .into_iter()
+            .map(|(_, instructions)| {
+                instructions
+                    .iter()
+                    .map(|instruction| instruction.address)
+                    .collect()
+            })
+            .collect();
+        assert_eq!(addresses, [vec![0x0, 0x100, 0x20], vec![0x0, 0x100]]);
+
+        assert!(TraceFile::try_from("compare 'a' {\n    tailcall 'x'\n}\n").is_err());
+
+        // the loop in 'd' runs once, the trailing range is never reached
+        let instruction_counts: Vec<usize> = trace_file
+            .static_stats()
+            .iter()
+            .map(|(_, stats)| stats.instruction_count)
+            .collect();
+        assert_eq!(instruction_counts, [3, 2]);
+    }
+
+    #[test]
+    fn recursive_calls_are_rejected() {
+        for input in [
+            "compare 'a' {\n    a()\n}\n",
+            "compare 'a' {\n    0x0..32..0x4\n    tailcall 'a'\n}\n",
+            "'b' {\n    loop(2) {\n        c()\n    }\n}\n\n'c' {\n    b()\n}\n\ncompare 'a' {\n    b()\n}\n",
+        ] {
+            let error = TraceFile::try_from(input).unwrap_err().to_string();
+            assert!(error.starts_with("recursive call"), "{error}");
+        }
+
+        let error = TraceFile::try_from("'b' {\n    c()\n}\n\n'c' {\n    b()\n}\n")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(error, "recursive call 'b' -> 'c' -> 'b'");
+    }
+
+    #[test]
+    fn large_generated_trace_parses() {
+        let input = generate_large(3, 16 * 1024);
+        assert!(input.len() >= 16 * 1024);
+        assert_eq!(generate_large(3, 16 * 1024), input);
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn parse_throughput() {
+        let input = generate_large(3, 16 * 1024);
+        assert!(super::parse_throughput(&input, 2).unwrap() > 0.0);
+        assert!(super::parse_throughput("compare 'a' {\n    0x0..\n}\n", 1).is_err());
+    }
+
+    #[test]
+    fn stride_emits_strided_loads() {
+        let (_, events) =
+            TraceFile::try_from("compare 'a' {\n    stride(0x0, 4, 0x40)\n    0x0..32..0x4\n}\n")
+                .unwrap()
+                .into_events(usize::MAX)
+                .next()
+                .unwrap();
+        assert_eq!(
+            events.collect::<Vec<_>>(),
+            [
+                TraceEvent::Load(0x0),
+                TraceEvent::Load(0x40),
+                TraceEvent::Load(0x80),
+                TraceEvent::Load(0xC0),
+                TraceEvent::Instruction(Instruction {
+                    address: 0x0,
+                    length: 32
+                }),
+            ]
+        );
+
+        assert!(
+            TraceFile::try_from("compare 'a' {\n    stride(0x0, 3, 0xFFFFFFFFFFFFFFFF)\n}\n")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn loop_break_mean_iterations() {
+        let input = "compare 'a' {\n    loop(1000) break(0.1) {\n        0x0..32..0x4\n    }\n}\n";
+        let runs = 2000;
+        let iterations: usize = (0..runs)
+            .map(|seed| {
+                let (_, events) = TraceFile::try_from(input)
+                    .unwrap()
+                    .into_events_seeded(usize::MAX, seed)
+                    .next()
+                    .unwrap();
+                events.count()
+            })
+            .sum();
+
+        // geometric distribution, the 1000 iterations are practically never reached
+        let mean = iterations as f64 / runs as f64;
+        assert!((mean - 1.0 / 0.1).abs() < 0.75, "mean of {mean} iterations");
+
+        let trace_file = TraceFile::try_from(input).unwrap();
+        assert_eq!(trace_file.static_stats()[0].1.instruction_count, 1000);
+        assert!(
+            TraceFile::try_from(
+                "compare 'a' {\n    loop(3) break(1.5) {\n        0x0..32..0x4\n    }\n}\n"
+            )
+            .is_err()
+        );
+
+        // the breaks are drawn per iteration, so an almost endless loop stops at the limit
+        let (_, events) = TraceFile::try_from(
+            "compare 'a' {\n    loop(inf) break(0.000000001) {\n        0x0..32..0x4\n    }\n}\n",
+        )
+        .unwrap()
+        .into_events(100)
+        .next()
+        .unwrap();
+        assert_eq!(events.count(), 100);
+    }
+
+    #[test]
+    fn five_deep_nesting() {
+        let mut input = String::from("'a' {\n    0x0..32..0x4\n}\n\ncompare 'b' {\n");
+        for depth in 1..=5 {
+            input.push_str(&format!("{}loop(2) {{\n", "    ".repeat(depth)));
+        }
+        input.push_str(&format!("{}a()\n", "    ".repeat(6)));
+        for depth in (1..=5).rev() {
+            input.push_str(&format!("{}}}\n", "    ".repeat(depth)));
+        }
+        input.push_str("}\n");
+
+        assert_eq!(
+            TraceFile::try_from(input.as_str())
+                .unwrap()
+                .max_nesting_depth(),
+            5
+        );
+        assert!(TraceFile::try_from_with_max_depth(&input, 5).is_ok());
+        assert!(TraceFile::try_from_with_max_depth(&input, 4).is_err());
+    }
+
+    #[test]
+    fn flat_iter_tags_instructions() {
+        let trace_file = TraceFile::try_from(
+            "compare 'b' {\n    0x0..32..0x8\n}\n\n'x' {\n    0x40..32..0x44\n}\n\ncompare 'a' {\n    0x20..32..0x24\n}\n",
+        )
+        .unwrap();
+        let flat: Vec<(&str, usize)> = trace_file
+            .into_flat_iter()
+            .map(|(name, instruction)| (name, instruction.address))
+            .collect();
+
+        assert_eq!(flat, [("b", 0x0), ("b", 0x4), ("a", 0x20)]);
+    }
+
+    #[test]
+    fn overlapping_ranges() {
+        let trace_file = TraceFile::try_from(
+            "compare 'a' {\n    0x0..32..0x100\n    0x80..32..0x180\n    0x180..32..0x200\n}\n",
+        )
+        .unwrap();
+
+        assert_eq!(trace_file.overlapping_ranges().len(), 1);
+        assert!(trace_file.overlapping_ranges()[0].contains("'a'"));
+        assert!(
+            TraceFile::try_from("compare 'a' {\n    0x0..32..0x80\n    0x80..32..0x100\n}\n")
+                .unwrap()
+                .overlapping_ranges()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected_while_parsing() {
+        let depth = 5000;
+        let input = format!(
+            "'a' {{\n{}0x0..32..0x4\n{}}}\n",
+            "loop(1) {\n".repeat(depth),
+            "}\n".repeat(depth)
+        );
+
+        assert!(matches!(
+            TraceFile::try_from(input.as_str()),
+            Err(TraceParseError::SyntaxError(_))
+        ));
+        assert!(matches!(
+            TraceFile::try_from_with_max_depth(&input, 10),
+            Err(TraceParseError::SyntaxError(message)) if message.contains("limit of 10 levels")
+        ));
+    }
 }